@@ -0,0 +1,218 @@
+use std::path::Path;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{token, Ident, LitStr, Token};
+use syn::parse::{Parse, ParseStream};
+
+use crate::interface::Interface;
+
+/*
+ * Arguments of #[Converse(...)]:
+ *
+ *     #[Converse(name = "playlist", path = "/run/playlist", codec = cbor,
+ *                client = "PlaylistClient", server = "PlaylistServer",
+ *                module = "ipc", parts(admin, stats))]
+ *
+ * all optional. #[Converse(playlist)] is short for name = "playlist".
+ *
+ * Other impl blocks of the type add their methods to the service with
+ *
+ *     #[Converse(part = "admin", module = "ipc")]
+ *
+ * giving the same client and module as the block naming them in parts.
+ */
+#[derive(Default)]
+pub struct Args {
+    /* of the process directory and the abstract socket */
+    name: Option<String>,
+    /* process directory to use instead of one named after the service */
+    path: Option<String>,
+    codec: Option<Codec>,
+    client: Option<Ident>,
+    server: Option<Ident>,
+    /* put the generated types in a module of this name */
+    module: Option<Ident>,
+    /* this block is a part of the service, named in parts(...) of another */
+    part: Option<Ident>,
+    /* the parts whose methods the server dispatches to as well */
+    parts: Vec<Ident>,
+}
+
+impl Args {
+    /*
+     * Name what wasn't named after the implementing type or the trait,
+     * Playlist gives "playlist", PlaylistClient and PlaylistServer.
+     */
+    pub fn defaults(mut self, interface: &Interface) -> Result<Self, syn::Error> {
+
+        if self.name.is_some() && self.client.is_some() && self.server.is_some() {
+            return Ok(self);
+        }
+
+        let ident = match interface.ident() {
+            Some(x) => x,
+            None => return Err(syn::Error::new_spanned(&interface.server().self_ty,
+                "can't name a service after this type, give `name`, `client` and `server` in #[Converse(...)]")),
+        };
+
+        let named = |suffix| Ident::new(&format!("{}{}", ident, suffix), ident.span());
+
+        self.name.get_or_insert_with(|| ident.to_string().to_lowercase());
+        self.client.get_or_insert_with(|| named("Client"));
+        self.server.get_or_insert_with(|| named("Server"));
+
+        Ok(self)
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_ref().map(String::as_str).unwrap_or("")
+    }
+
+    /* what the process directory is created from, see ProcessDirectory::new */
+    pub fn directory(&self) -> &str {
+        self.path.as_ref().map(String::as_str).unwrap_or(self.name())
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec.clone().unwrap_or_default()
+    }
+
+    pub fn client(&self) -> Ident {
+        self.client.clone().unwrap_or_else(|| Ident::new("Client", proc_macro2::Span::call_site()))
+    }
+
+    pub fn server(&self) -> Ident {
+        self.server.clone().unwrap_or_else(|| Ident::new("Server", proc_macro2::Span::call_site()))
+    }
+
+    pub fn module(&self) -> Option<&Ident> {
+        self.module.as_ref()
+    }
+
+    pub fn part(&self) -> Option<&Ident> {
+        self.part.as_ref()
+    }
+
+    pub fn parts(&self) -> &Vec<Ident> {
+        &self.parts
+    }
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+
+        let mut args = Args::default();
+
+        /* a lone name, not the key of name = .. or parts(..) */
+        if input.peek(Ident) && !input.peek2(Token![=]) && !input.peek2(token::Paren) {
+            let name: Ident = input.parse()?;
+            args.name = Some(name.to_string());
+
+            if !input.is_empty() {
+                return Err(input.error("expected only a name, or key = value arguments"));
+            }
+        }
+
+        while !input.is_empty() {
+
+            let key: Ident = input.parse()?;
+
+            if key == "parts" {
+                let list;
+                syn::parenthesized!(list in input);
+
+                if !args.parts.is_empty() {
+                    return Err(syn::Error::new_spanned(&key, "`parts` given more than once"));
+                }
+
+                args.parts = list.parse_terminated::<_, Token![,]>(Ident::parse)?.into_iter().collect();
+
+                if !input.is_empty() {
+                    input.parse::<Token![,]>()?;
+                }
+                continue;
+            }
+
+            input.parse::<Token![=]>()?;
+
+            let duplicate = match key.to_string().as_str() {
+                "name" => args.name.replace(input.parse::<LitStr>()?.value()).is_some(),
+                "path" => {
+                    let lit: LitStr = input.parse()?;
+
+                    /* a relative one would end up wherever the program is started */
+                    if !Path::new(&lit.value()).is_absolute() {
+                        return Err(syn::Error::new_spanned(&lit, "path must be absolute"));
+                    }
+
+                    args.path.replace(lit.value()).is_some()
+                },
+                "codec" => args.codec.replace(input.parse()?).is_some(),
+                "client" => args.client.replace(type_name(input)?).is_some(),
+                "server" => args.server.replace(type_name(input)?).is_some(),
+                "module" => args.module.replace(type_name(input)?).is_some(),
+                "part" => args.part.replace(type_name(input)?).is_some(),
+                _ => return Err(syn::Error::new_spanned(&key,
+                    "unknown argument, expected `name`, `path`, `codec`, `client`, `server`, `module`, `part` or `parts`")),
+            };
+
+            if duplicate {
+                return Err(syn::Error::new_spanned(&key, format!("`{}` given more than once", key)));
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        if let (Some(part), false) = (&args.part, args.parts.is_empty()) {
+            return Err(syn::Error::new_spanned(part, "a part can't have parts of its own"));
+        }
+
+        Ok(args)
+    }
+}
+
+/* how arguments and return values are encoded */
+#[derive(Clone)]
+pub enum Codec {
+    Cbor,
+}
+
+impl Codec {
+    /* module with to_vec and from_slice */
+    pub fn module(&self) -> TokenStream {
+        match self {
+            Codec::Cbor => quote! { ::converse::serde_cbor },
+        }
+    }
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Codec::Cbor
+    }
+}
+
+impl Parse for Codec {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+
+        match ident.to_string().as_str() {
+            "cbor" => Ok(Codec::Cbor),
+            _ => Err(syn::Error::new_spanned(&ident, "unknown codec, expected `cbor`")),
+        }
+    }
+}
+
+/* "PlaylistClient", spanned to the string */
+fn type_name(input: ParseStream) -> syn::Result<Ident> {
+    let lit: LitStr = input.parse()?;
+
+    match syn::parse_str::<Ident>(&lit.value()) {
+        Ok(x) => Ok(Ident::new(&x.to_string(), lit.span())),
+        Err(_) => Err(syn::Error::new_spanned(&lit, "expected an identifier")),
+    }
+}
+
@@ -2,24 +2,38 @@ use proc_macro2::TokenStream;
 use quote::quote;
 use syn;
 
-use crate::structure::Structure;
+use crate::args::Args;
+use crate::interface::Interface;
+use crate::structure::{Method, Structure};
 
 pub struct Client {
     structure: Structure,
     directory: String,
+    name: String,
+    codec: TokenStream,
+    /* trait the client implements, and if it was defined with #[Converse] */
+    implements: Option<(syn::Path, Option<syn::token::Unsafe>)>,
+    definition: bool,
+    /* only add methods to the client of another block, see Args */
+    is_part: bool,
 }
 
 impl Client {
-    pub fn new(item: &syn::ItemImpl, directory: String) -> Self {
+    pub fn new(interface: &Interface, args: &Args) -> Self {
 
-        let ident = syn::Ident::new("Client", proc_macro2::Span::call_site());
-        let mut structure = Structure::from_impl(ident, item.clone());
+        let mut structure = Structure::from_impl(args.client(), interface.client().clone());
 
-        structure.member(quote! { proc: ::converse::procdir::ProcessDirectory });
+        /* parts of the service make their calls through it from other modules */
+        structure.member(quote! { pub(crate) channel: ::converse::client::Channel });
 
         Client {
             structure: structure,
-            directory: directory,
+            directory: args.directory().to_string(),
+            name: args.name().to_string(),
+            codec: args.codec().module(),
+            implements: interface.implements().map(|x| (x.clone(), interface.unsafety().cloned())),
+            definition: interface.is_definition(),
+            is_part: args.part().is_some(),
         }
     }
 }
@@ -28,30 +42,41 @@ impl Client {
     /* server struct and impls */
     pub fn tokens(&self) -> TokenStream {
 
-        let decl = self.structure.declare();
-        let initializer = self.initializer();
+        let (decl, initializer) = match self.is_part {
+            true => (quote!(), quote!()),
+            false => (self.structure.declare(), self.initializer()),
+        };
         let implementations = self.implementations();
+        let batch = self.batch();
+        let transaction = self.transaction();
+        let trait_impl = self.trait_impl();
 
         quote! {
             #decl
             #initializer
             #implementations
+            #trait_impl
+            #batch
+            #transaction
         }
     }
 
     fn initializer(&self) -> TokenStream {
 
         let dir = &self.directory;
+        let name = &self.name;
         let ty = self.structure.ty();
 
-        /* proc is declared below in the client function */
+        /* transport is declared below in the client functions */
         let mut fields = syn::punctuated::Punctuated::new();
-        fields.push( quote! { proc: proc } );
+        fields.push( quote! { channel: ::converse::client::Channel::new(transport) } );
 
         let auto = self.structure.generics().generated();
-        /* this actually creates the struct */
         let client = self.structure.initialize(fields);
 
+        let mut with = auto.clone();
+        with.push(quote! { C: ::converse::transport::Connector + 'static });
+
         let body = quote! {
             pub fn client<#auto>() -> Result<#ty, ::converse::error::Error> {
                 let proc = ::converse::procdir::ProcessDirectory::new(#dir)?;
@@ -61,22 +86,113 @@ impl Client {
                         format!("Socket file '{}' does not exist.", proc.socket().display())));
                 }
 
+                let transport: Box<dyn ::converse::transport::Connector> =
+                    Box::new(::converse::transport::unix::UnixConnector::new(proc.socket())?);
+
+                Ok(#client)
+            }
+
+            /* like client(), but launch the server first if it isn't running */
+            pub fn client_autostart<#auto>(mut autostart: ::converse::autostart::AutoStart) -> Result<#ty, ::converse::error::Error> {
+                let proc = ::converse::procdir::ProcessDirectory::new(#dir)?;
+                autostart.start(&proc)?;
+
+                let transport: Box<dyn ::converse::transport::Connector> =
+                    Box::new(::converse::transport::unix::UnixConnector::new(proc.socket())?);
+
+                Ok(#client)
+            }
+
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn client_abstract<#auto>() -> Result<#ty, ::converse::error::Error> {
+                let transport: Box<dyn ::converse::transport::Connector> =
+                    Box::new(::converse::transport::unix::UnixConnector::new_abstract(#name)?);
+
+                Ok(#client)
+            }
+
+            /* spawn `command` and talk to its serve_stdio, the child lives as long as the client */
+            pub fn client_from_child<#auto>(command: ::std::process::Command) -> Result<#ty, ::converse::error::Error> {
+                let transport: Box<dyn ::converse::transport::Connector> =
+                    Box::new(::converse::transport::stdio::ChildConnector::spawn(command)?);
+
+                Ok(#client)
+            }
+
+            /* connect over any transport, e.g. tcp or tls */
+            pub fn client_with<#with>(connector: C) -> Result<#ty, ::converse::error::Error> {
+                let transport: Box<dyn ::converse::transport::Connector> = Box::new(connector);
+
                 Ok(#client)
             }
         };
 
-        self.structure.implement_parent(body)
+        /* a trait definition has no type to put them on */
+        match self.definition {
+            true => self.structure.implement(body),
+            false => self.structure.implement_parent(body),
+        }
     }
 
     fn implementations(&self) -> TokenStream {
 
-        let endpoints = self.endpoints();
+        let endpoints = self.endpoints(quote! { self.channel }, true);
+
+        let transaction = self.transaction_ident();
+        let ty = self.structure.generics().params();
+
+        let batch = self.batch_ident();
+        let mut params = syn::punctuated::Punctuated::<_, syn::token::Comma>::new();
+        params.push(quote! { '_ });
+        params.extend(self.structure.generics().params());
+        params.push(quote! { () });
+
+        if self.is_part {
+            return self.structure.implement(endpoints);
+        }
 
         let body = quote! {
-            fn exit(&mut self) -> Result<(), ::converse::error::Error> {
-                let mut stream = std::os::unix::net::UnixStream::connect(self.proc.socket())?;
-                ::converse::protocol::IPCRequest::new(0, vec![]).write(&mut stream)?;
-                Ok(())
+            pub fn exit(&mut self) -> Result<(), ::converse::error::Error> {
+                self.channel.exit()
+            }
+
+            /* connect, read, write and per call timeouts */
+            pub fn timeouts(&mut self) -> &mut ::converse::client::Timeouts {
+                self.channel.timeouts()
+            }
+
+            /* how #[converse(idempotent)] methods are retried on connection failures */
+            pub fn retry(&mut self) -> &mut ::converse::client::Retry {
+                self.channel.retry()
+            }
+
+            /*
+             * keep one connection open and send every call over it, so calls
+             * made from several threads are in flight at the same time. The
+             * server should use run_concurrent, run() serves nobody else
+             * while the connection is open.
+             */
+            pub fn multiplex(&mut self) -> Result<(), ::converse::error::Error> {
+                self.channel.multiplex()
+            }
+
+            /* queue calls to be made in one round trip */
+            pub fn batch(&self) -> #batch<#params> {
+                #batch {
+                    client: self,
+                    batch: ::converse::client::Batch::new(),
+                }
+            }
+
+            /*
+             * hold the server's state exclusively for the calls made on the
+             * returned transaction until it is committed or aborted
+             */
+            pub fn transaction(&self) -> Result<#transaction<#ty>, ::converse::error::Error> {
+                Ok(#transaction {
+                    transaction: self.channel.begin()?,
+                    client: ::std::marker::PhantomData,
+                })
             }
 
             #endpoints
@@ -85,47 +201,308 @@ impl Client {
         self.structure.implement(body)
     }
 
-    fn endpoints(&self) -> TokenStream {
+    /*
+     * The trait, implemented by calling the server. A method returning some
+     * Result<T, E> returns our errors as Err(E::from(error)), so E has to
+     * implement From<converse::error::Error>. Any other method panics on them.
+     */
+    fn trait_impl(&self) -> TokenStream {
+
+        let (path, unsafety) = match self.implements {
+            Some(ref x) => x,
+            None => return quote!(),
+        };
+
+        let ident = self.structure.ident();
+        let generics = self.structure.generics();
+        let decls = generics.decls();
+        let params = generics.params();
+        let where_clause = generics.where_clause();
 
+        let imp = self.structure.implementation();
+        let methods = imp.methods().iter().map(|x| {
+
+            let method = x.ident();
+            let args = x.args();
+            let call = quote! { Self::#method(self, #args) };
+
+            let error = match x.returns_result() {
+                true => quote! { Err(::std::convert::From::from(__converse_e)) },
+                false => {
+                    let message = format!("{}: {{}}", method);
+                    quote! { panic!(#message, __converse_e) }
+                },
+            };
+
+            x.decl_trait(quote! {
+                match #call {
+                    Ok(__converse_ret) => __converse_ret,
+                    Err(__converse_e) => #error,
+                }
+            })
+        });
+
+        quote! {
+            /*
+             * calls the server. Methods returning a Result return transport
+             * errors as Err, the others panic on a failed call, including a
+             * dropped connection or a timeout.
+             */
+            #unsafety impl<#decls> #path for #ident<#params> #where_clause {
+                #(#methods)*
+            }
+        }
+    }
+
+    fn transaction_ident(&self) -> syn::Ident {
+        let ident = self.structure.ident();
+        syn::Ident::new(&format!("{}Transaction", ident), ident.span())
+    }
+
+    /* same methods as the client, made inside a transaction */
+    fn transaction(&self) -> TokenStream {
+
+        let ident = self.transaction_ident();
+        let client = self.structure.ty();
+        let generics = self.structure.generics();
+        let decls = generics.decls();
+        let params = generics.params();
+        let where_clause = generics.where_clause();
+
+        let endpoints = self.endpoints(quote! { self.transaction }, false);
+
+        if self.is_part {
+            return quote! {
+                impl<#decls> #ident<#params> #where_clause {
+                    #endpoints
+                }
+            };
+        }
+
+        quote! {
+            pub struct #ident<#decls> #where_clause {
+                pub(crate) transaction: ::converse::client::Transaction,
+                client: ::std::marker::PhantomData<#client>,
+            }
+
+            impl<#decls> #ident<#params> #where_clause {
+                /* keep the changes and let other clients at the state again */
+                pub fn commit(self) -> Result<(), ::converse::error::Error> {
+                    self.transaction.commit()
+                }
+
+                /*
+                 * roll back if the server was built with rollback(), otherwise
+                 * the changes are kept and the state only released
+                 */
+                pub fn abort(self) -> Result<(), ::converse::error::Error> {
+                    self.transaction.abort()
+                }
+
+                #endpoints
+            }
+        }
+    }
+
+    fn batch_ident(&self) -> syn::Ident {
+        let ident = self.structure.ident();
+        syn::Ident::new(&format!("{}Batch", ident), ident.span())
+    }
+
+    /*
+     * Builder returned by batch(). Each method queues a call and adds its
+     * result type to `Results`, send() makes the calls and returns them all:
+     *
+     *     let (list, data) = client.batch().list().data().send()?;
+     */
+    fn batch(&self) -> TokenStream {
+
+        let ident = self.batch_ident();
+        let client = self.structure.ty();
+        let generics = self.structure.generics();
+        let where_clause = generics.where_clause();
+
+        let mut decls = syn::punctuated::Punctuated::<_, syn::token::Comma>::new();
+        decls.push(quote! { 'converse });
+        decls.extend(generics.decls());
+        decls.push(quote! { Results });
+
+        let mut bounded = decls.clone();
+        bounded.pop();
+        bounded.push(quote! { Results: 'converse });
+
+        let mut params = syn::punctuated::Punctuated::<_, syn::token::Comma>::new();
+        params.push(quote! { 'converse });
+        params.extend(generics.params());
+
+        let codec = &self.codec;
+        let imp = self.structure.implementation();
+        let methods: TokenStream = imp.methods().iter().map(|x| {
+
+            let key = key(x);
+            let ret = x.ret();
+            let args = x.args();
+            let args = args.iter().map(|x| quote! { #codec::to_vec(&#x)? });
+
+            /* errors are kept for send() to return */
+            let body = quote! {
+                let (__converse_key, __converse_argv) = match #key {
+                    Ok(__converse_key) => (__converse_key, (|| -> Result<Vec<Vec<u8>>, ::converse::error::Error> {
+                        Ok(vec![#(#args),*])
+                    })()),
+                    Err(__converse_e) => (0, Err(__converse_e)),
+                };
+
+                #ident {
+                    client: self.client,
+                    batch: self.batch.push(__converse_key, __converse_argv),
+                }
+            };
+
+            let next = quote! { #ident<#params, <Results as ::converse::client::Append<#ret>>::Output> };
+
+            let mut bounds = bounds(x);
+            bounds.push(quote! { Results: ::converse::client::Append<#ret> });
+            let bounds = quote! { #bounds };
+
+            x.decl_on(quote! { self }, next, bounds, body)
+
+        }).collect();
+
+        if self.is_part {
+            return quote! {
+                impl<#bounded> #ident<#params, Results> #where_clause {
+                    #methods
+                }
+            };
+        }
+
+        quote! {
+            pub struct #ident<#decls> #where_clause {
+                pub(crate) client: &'converse #client,
+                pub(crate) batch: ::converse::client::Batch<'converse, Results>,
+            }
+
+            impl<#bounded> #ident<#params, Results> #where_clause {
+                pub fn send(self) -> Result<Results, ::converse::error::Error> {
+                    self.batch.send(&self.client.channel)
+                }
+
+                #methods
+            }
+        }
+    }
+
+    /* methods making calls through `target`, retrying idempotent ones if `retry` */
+    fn endpoints(&self, target: TokenStream, retry: bool) -> TokenStream {
+
+        let codec = &self.codec;
         let imp = self.structure.implementation();
 
         /*
          * for each method, make a new method of the same name
-         * which connects, deserializes args, serializes result
+         * which serializes args, makes the call, deserializes the result
          */
-        imp.methods().iter().enumerate().map(|(i,x)| {
+        imp.methods().iter().map(|x| {
 
             let ret = x.ret();
             let args = x.args();
 
-            let idx = (i + 1) as u32;
+            let key = key(x);
+            let bounds = bounds(x);
             let argc = args.len();
 
-            let init = quote! { let mut argv = Vec::with_capacity(#argc); };
+            /* reserved names, so the method's own parameters aren't shadowed */
+            let init = quote! {
+                let __converse_key = #key?;
+                let mut __converse_argv = Vec::with_capacity(#argc);
+            };
 
             let argv = args.iter()
                 .map(|arg| quote! {
-                    argv.push(::converse::serde_cbor::to_vec(&#arg)?);
+                    __converse_argv.push(#codec::to_vec(&#arg)?);
                 })
                 .fold(init, |acc, tok| quote! {
                      #acc #tok
                  });
 
-            let body = quote! {
-                let mut stream = std::os::unix::net::UnixStream::connect(self.proc.socket())?;
+            if x.attrs().oneway {
 
+                let body = quote! {
+                    #argv
+
+                    #target.send(__converse_key, __converse_argv)
+                };
+
+                return x.decl(quote! { Result<(), ::converse::error::Error> }, quote! { #bounds }, body);
+            }
+
+            /* only calls that are safe to replay go through the retry policy */
+            let call = if retry && x.attrs().idempotent {
+                quote! { call_idempotent }
+            } else {
+                quote! { call }
+            };
+
+            let body = quote! {
                 #argv
 
-                ::converse::protocol::IPCRequest::new(#idx, argv).write(&mut stream)?;
-                let res = ::converse::protocol::IPCBuffer::read(&mut stream)?;
+                let __converse_res = #target.#call(__converse_key, __converse_argv)?;
 
-                Ok(::converse::serde_cbor::from_slice(&res.data)?)
+                Ok(#codec::from_slice(&__converse_res)?)
             };
 
 
-            x.decl(quote! { Result<#ret, ::converse::error::Error> }, body)
+            x.decl(quote! { Result<#ret, ::converse::error::Error> }, quote! { #bounds }, body)
 
         }).collect()
     }
 }
 
+/*
+ * What the types a generic method is called with need on the client: they go
+ * over the wire one way or the other, and pick the key by TypeId.
+ */
+fn bounds(method: &Method) -> syn::punctuated::Punctuated<TokenStream, syn::token::Comma> {
+    method.type_params().into_iter()
+        .map(|x| quote! {
+            #x: ::converse::serde::Serialize + ::converse::serde::de::DeserializeOwned + 'static
+        })
+        .collect()
+}
+
+/*
+ * Result<u32, Error> of the key to call `method` by. Generic methods have one
+ * per #[converse(instantiate(...))], picked by the types they're called with.
+ */
+fn key(method: &Method) -> TokenStream {
+
+    let params = method.type_params();
+
+    if params.is_empty() {
+        let key = method.instances()[0].key;
+        return quote! { Ok::<u32, ::converse::error::Error>(#key) };
+    }
+
+    let branches = method.instances().iter().map(|x| {
+        let key = x.key;
+        let types = x.types();
+        quote! {
+            if __converse_id == ::std::any::TypeId::of::<(#(#types,)*)>() {
+                Ok(#key)
+            }
+        }
+    });
+
+    let error = format!("{} isn't instantiated for these types, see #[converse(instantiate(...))]", method.ident());
+
+    quote! {
+        {
+            let __converse_id = ::std::any::TypeId::of::<(#(#params,)*)>();
+
+            #(#branches else)* {
+                Err(::converse::error::Error::Client(#error.to_string()))
+            }
+        }
+    }
+}
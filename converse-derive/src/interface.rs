@@ -0,0 +1,284 @@
+use proc_macro2::TokenStream;
+use syn::{
+    Attribute, FnArg, GenericParam, Generics, Ident, ImplItem, ImplItemMethod,
+    Item, ItemImpl, ItemTrait, Path, Token, TraitItem, Type
+};
+use syn::parse::{ParseStream, Parser};
+
+use crate::structure::{self, MethodAttrs};
+
+/*
+ * What #[Converse] was placed on. Inherent impls are served as they are, a
+ * trait definition is served for any type implementing it. The client of a
+ * trait, or of an impl of one, implements that trait as well.
+ */
+pub struct Interface {
+    /* the methods the server calls, with the generics of the server */
+    server: ItemImpl,
+    /* the same methods, with the generics of the client */
+    client: ItemImpl,
+    /* trait the client implements */
+    implements: Option<Path>,
+    unsafety: Option<Token![unsafe]>,
+    /* a trait definition, there's no type to put the constructors on */
+    definition: bool,
+    ident: Option<Ident>,
+}
+
+impl Interface {
+    pub fn from_item(item: &Item) -> Result<Self, syn::Error> {
+        match item {
+            Item::Impl(x) => Self::from_impl(x),
+            Item::Trait(x) => Ok(Self::from_trait(x)),
+            _ => Err(syn::Error::new(proc_macro2::Span::call_site(),
+                "#[Converse] must be placed on an impl block or a trait")),
+        }
+    }
+
+    fn from_impl(imp: &ItemImpl) -> Result<Self, syn::Error> {
+
+        let mut imp = imp.clone();
+
+        let implements = match imp.trait_.take() {
+            Some((Some(bang), _, _)) => return Err(syn::Error::new_spanned(bang, "negative impls can't be served")),
+            Some((None, path, _)) => Some(path),
+            None => None,
+        };
+
+        /* every method of a trait is public */
+        if implements.is_some() {
+            for item in imp.items.iter_mut() {
+                if let ImplItem::Method(x) = item {
+                    x.vis = syn::parse_quote!(pub);
+                }
+            }
+        }
+
+        /* only the client's impl of the trait is unsafe, the generated impls are inherent */
+        let unsafety = imp.unsafety.take();
+
+        Ok(Interface {
+            ident: self_ident(&imp).cloned(),
+            client: imp.clone(),
+            unsafety: unsafety,
+            server: imp,
+            implements: implements,
+            definition: false,
+        })
+    }
+
+    /*
+     * trait Playlist { .. } is served as if it was
+     *
+     *     impl<ConverseState: Playlist> ConverseState { .. }
+     *
+     * the client only takes the generics of the trait.
+     */
+    fn from_trait(def: &ItemTrait) -> Self {
+
+        let items: Vec<ImplItem> = def.items.iter()
+            .filter_map(|x| match x {
+                TraitItem::Method(x) => Some(ImplItem::Method(ImplItemMethod {
+                    attrs: x.attrs.clone(),
+                    vis: syn::parse_quote!(pub),
+                    defaultness: None,
+                    sig: x.sig.clone(),
+                    /* never emitted, the server calls the trait's */
+                    block: x.default.clone().unwrap_or_else(|| syn::parse_quote!({})),
+                })),
+                _ => None,
+            })
+            .collect();
+
+        let ident = &def.ident;
+        let (_, params, _) = def.generics.split_for_impl();
+        let path: Path = syn::parse_quote!(#ident #params);
+
+        let mut client: ItemImpl = syn::parse_quote!(impl #path {});
+        client.generics = def.generics.clone();
+        client.items = items;
+
+        let mut server: ItemImpl = syn::parse_quote!(impl ConverseState {});
+        server.generics = def.generics.clone();
+        server.generics.params.push(syn::parse_quote!(ConverseState: #path));
+        server.items = client.items.clone();
+
+        Interface {
+            server: server,
+            client: client,
+            implements: Some(path),
+            unsafety: def.unsafety,
+            definition: true,
+            ident: Some(def.ident.clone()),
+        }
+    }
+
+    pub fn server(&self) -> &ItemImpl {
+        &self.server
+    }
+
+    pub fn client(&self) -> &ItemImpl {
+        &self.client
+    }
+
+    pub fn implements(&self) -> Option<&Path> {
+        self.implements.as_ref()
+    }
+
+    pub fn unsafety(&self) -> Option<&Token![unsafe]> {
+        self.unsafety.as_ref()
+    }
+
+    pub fn is_definition(&self) -> bool {
+        self.definition
+    }
+
+    /* what the service is named after, Playlist in impl<T> Playlist<T> or trait Playlist */
+    pub fn ident(&self) -> Option<&Ident> {
+        self.ident.as_ref()
+    }
+
+    /* see structure::check, and what the client can't implement the trait with */
+    pub fn check(&self, item: &Item) -> Vec<syn::Error> {
+
+        let mut errors = structure::check(&self.server);
+
+        if let Item::Trait(def) = item {
+            for x in def.items.iter() {
+                match x {
+                    TraitItem::Method(x) => {
+                        /* attribute errors are reported above */
+                        let skipped = MethodAttrs::from_attrs(&x.attrs).map_or(false, |x| x.skip);
+
+                        if skipped && x.default.is_none() {
+                            errors.push(syn::Error::new_spanned(&x.sig,
+                                "skipped methods need a default body, the client implements the trait with it"));
+                        }
+                    },
+                    _ => errors.push(syn::Error::new_spanned(x, "the client can only implement the methods of a trait")),
+                }
+            }
+        }
+
+        if self.implements.is_none() {
+            return errors;
+        }
+
+        let methods = self.server.items.iter().filter_map(|x| match x {
+            ImplItem::Method(x) => Some(x),
+            _ => None,
+        });
+
+        for method in methods {
+
+            let attrs = match MethodAttrs::from_attrs(&method.attrs) {
+                Ok(ref x) if x.exported(method) => x.clone(),
+                _ => continue,
+            };
+
+            let decl = &method.sig.decl;
+
+            let receiver = match decl.inputs.first().map(|x| x.into_value()) {
+                Some(FnArg::SelfRef(_)) | Some(FnArg::SelfValue(_)) => true,
+                _ => false,
+            };
+
+            if !receiver {
+                errors.push(syn::Error::new_spanned(&method.sig.ident,
+                    "the client can't implement trait methods without self, skip them with #[converse(skip)]"));
+            }
+
+            /* without instantiate(...) structure::check already complained */
+            if decl.generics.type_params().next().is_some() && !attrs.instantiate.is_empty() {
+                errors.push(syn::Error::new_spanned(&decl.generics,
+                    "the client can't implement generic trait methods, skip them with #[converse(skip)]"));
+            }
+        }
+
+        errors
+    }
+}
+
+/*
+ * The item #[Converse] was placed on. syn only takes const parameters after
+ * the types, and none at all on impls, so impls it can't parse are parsed
+ * again here with their parameters in any order.
+ */
+pub fn parse(tokens: TokenStream) -> Result<Item, syn::Error> {
+    match syn::parse2::<Item>(tokens.clone()) {
+        Ok(x) => Ok(x),
+        Err(e) => match parse_impl.parse2(tokens) {
+            Ok(x) => Ok(Item::Impl(x)),
+            Err(_) => Err(e),
+        },
+    }
+}
+
+fn parse_impl(input: ParseStream) -> syn::Result<ItemImpl> {
+
+    let mut attrs = input.call(Attribute::parse_outer)?;
+    let defaultness: Option<Token![default]> = input.parse()?;
+    let unsafety: Option<Token![unsafe]> = input.parse()?;
+    let impl_token: Token![impl] = input.parse()?;
+
+    let mut generics = Generics::default();
+    if input.peek(Token![<]) {
+        generics.lt_token = Some(input.parse()?);
+
+        while !input.peek(Token![>]) {
+            generics.params.push_value(input.parse::<GenericParam>()?);
+
+            if input.peek(Token![>]) {
+                break;
+            }
+            generics.params.push_punct(input.parse()?);
+        }
+
+        generics.gt_token = Some(input.parse()?);
+    }
+
+    let bang: Option<Token![!]> = input.parse()?;
+    let first: Type = input.parse()?;
+
+    let (trait_, self_ty) = match input.peek(Token![for]) {
+        true => {
+            let path = match first {
+                Type::Path(ref x) if x.qself.is_none() => x.path.clone(),
+                _ => return Err(syn::Error::new_spanned(&first, "expected a trait")),
+            };
+            (Some((bang, path, input.parse()?)), input.parse()?)
+        },
+        false => (None, first),
+    };
+
+    generics.where_clause = input.parse()?;
+
+    let content;
+    let brace_token = syn::braced!(content in input);
+    attrs.extend(content.call(Attribute::parse_inner)?);
+
+    let mut items = vec![];
+    while !content.is_empty() {
+        items.push(content.parse()?);
+    }
+
+    Ok(ItemImpl {
+        attrs: attrs,
+        defaultness: defaultness,
+        unsafety: unsafety,
+        impl_token: impl_token,
+        generics: generics,
+        trait_: trait_,
+        self_ty: Box::new(self_ty),
+        brace_token: brace_token,
+        items: items,
+    })
+}
+
+/* Playlist in impl<T> Playlist<T> */
+fn self_ident(imp: &ItemImpl) -> Option<&Ident> {
+    match *imp.self_ty {
+        Type::Path(ref x) if x.qself.is_none() => x.path.segments.last().map(|x| &x.into_value().ident),
+        _ => None,
+    }
+}
@@ -4,28 +4,99 @@ extern crate proc_macro;
 use quote::quote;
 use syn;
 
+mod args;
 mod server;
 mod client;
+mod interface;
 mod structure;
 
 #[allow(non_snake_case)]
 #[proc_macro_attribute]
 pub fn Converse(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
-    let ast: syn::Item = syn::parse(item).unwrap();
+    let ast = match interface::parse(item.into()) {
+        Ok(x) => x,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    /* method attributes are only meaningful to us */
+    let mut original = ast.clone();
+    match original {
+        syn::Item::Impl(ref mut x) => for item in x.items.iter_mut() {
+            if let syn::ImplItem::Method(x) = item {
+                x.attrs.retain(|x| !structure::is_converse_attr(x));
+            }
+        },
+        syn::Item::Trait(ref mut x) => for item in x.items.iter_mut() {
+            if let syn::TraitItem::Method(x) = item {
+                x.attrs.retain(|x| !structure::is_converse_attr(x));
+            }
+        },
+        _ => {},
+    }
+
+    let interface = match interface::Interface::from_item(&ast) {
+        Ok(x) => x,
+        Err(e) => {
+            let e = e.to_compile_error();
+            return quote!(#original #e).into();
+        },
+    };
+
+    /* keep the item so its own errors still show up next to ours */
+    let errors = interface.check(&ast);
+    if !errors.is_empty() {
+        let errors: proc_macro2::TokenStream = errors.iter().map(syn::Error::to_compile_error).collect();
+        return quote!(#original #errors).into();
+    }
 
-    let item_impl = match ast {
-        syn::Item::Impl(ref x) => { x },
-        _ => panic!("Server attribute must be placed on an impl!")
+    let args = syn::parse::<args::Args>(attr).and_then(|x| x.defaults(&interface));
+    let args = match args {
+        Ok(x) => x,
+        Err(e) => {
+            let e = e.to_compile_error();
+            return quote!(#original #e).into();
+        },
     };
 
-    let server = server::Server::new(&item_impl, attr.to_string()).tokens();
-    let client = client::Client::new(&item_impl, attr.to_string()).tokens();;
+    /* a trait has no type for parts to be impl blocks of */
+    if interface.is_definition() && (args.part().is_some() || !args.parts().is_empty()) {
+        let e = syn::Error::new(proc_macro2::Span::call_site(), "a trait can't have parts or be one, use impl blocks of a type");
+        let e = e.to_compile_error();
+        return quote!(#original #e).into();
+    }
+
+    let server = server::Server::new(&interface, &args).tokens();
+    let client = client::Client::new(&interface, &args).tokens();
+
+    let generated = match args.module() {
+        /* a part adds to what another block put in the module */
+        Some(module) if args.part().is_some() => quote! {
+            const _: () = {
+                use #module::*;
+
+                #server
+                #client
+            };
+        },
+        /* the impls on the original type work from inside the module too */
+        Some(module) => quote! {
+            pub mod #module {
+                use super::*;
+
+                #server
+                #client
+            }
+        },
+        None => quote! {
+            #server
+            #client
+        },
+    };
 
     let tokens = quote! {
-        #ast
-        #server
-        #client
+        #original
+        #generated
     };
 
     // println!("{}", tokens);
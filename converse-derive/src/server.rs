@@ -2,27 +2,46 @@ use proc_macro2::TokenStream;
 use quote::{quote, quote_spanned};
 use syn;
 
+use crate::args::Args;
+use crate::interface::Interface;
 use crate::structure::Structure;
 
 pub struct Server {
     structure: Structure,
     directory: String,
+    name: String,
+    codec: TokenStream,
+    state: Box<syn::Type>,
+    /* the state is any implementation of a trait, see Interface */
+    definition: bool,
+    /* registry name of the methods, and the other blocks' parts named in parts(...) */
+    part: String,
+    parts: Vec<syn::Ident>,
+    /* only the part of another block's service, see Args */
+    is_part: bool,
 }
 
 impl Server {
-    pub fn new(item: &syn::ItemImpl, directory: String) -> Self {
+    pub fn new(interface: &Interface, args: &Args) -> Self {
 
-        let ident = syn::Ident::new("Server", proc_macro2::Span::call_site());
-        let mut structure = Structure::from_impl(ident, item.clone());
+        let item = interface.server();
+        let mut structure = Structure::from_impl(args.server(), item.clone());
         let state_ty = &item.self_ty;
 
-        structure.member(quote! { proc: ::converse::procdir::ProcessDirectory });
-        structure.member(quote! { socket: ::std::os::unix::net::UnixListener  });
-        structure.member(quote! { state: #state_ty  });
+        structure.member(quote! { socket: Box<dyn ::converse::transport::Listener> });
+        /* &self methods share the state, &mut self methods lock it exclusively */
+        structure.member(quote! { shared: ::converse::server::Shared<#state_ty> });
 
         Server {
             structure,
-            directory,
+            directory: args.directory().to_string(),
+            name: args.name().to_string(),
+            codec: args.codec().module(),
+            state: item.self_ty.clone(),
+            definition: interface.is_definition(),
+            part: args.part().map(ToString::to_string).unwrap_or_else(|| args.name().to_string()),
+            parts: args.parts().clone(),
+            is_part: args.part().is_some(),
         }
     }
 }
@@ -30,6 +49,10 @@ impl Server {
 impl Server {
     pub fn tokens(&self) -> TokenStream {
 
+        if self.is_part {
+            return self.part_fn();
+        }
+
         let decl = self.structure.declare();
         let initializer = self.initializer();
         let implementations = self.implementations();
@@ -44,31 +67,100 @@ impl Server {
     fn initializer(&self) -> TokenStream {
 
         let dir = &self.directory;
+        let name = &self.name;
         let ty = self.structure.ty();
+        let concrete = self.structure.concrete_ty();
+
+        /* there's no type to put the constructors on for a trait, they take the state instead */
+        let state_ty = &self.state;
+        let (receiver, state) = match self.definition {
+            true => (quote! { state: #state_ty }, quote! { state }),
+            false => (quote! { self }, quote! { self }),
+        };
 
         /* proc is declared below in the client function */
         let mut fields = syn::punctuated::Punctuated::new();
-        fields.push( quote! { proc: proc } );
         fields.push( quote! { socket: socket } );
-        fields.push( quote! { state: self } );
+        fields.push( quote! { shared: ::converse::server::Shared::new(#state, <#concrete>::registry()?, proc) } );
 
         let auto = self.structure.generics().generated();
         /* this actually creates the struct */
         let server = self.structure.initialize(fields);
 
+        let mut with = auto.clone();
+        with.push(quote! { L: ::converse::transport::Listener + 'static });
+
         let body = quote! {
-            pub fn server<#auto>(self) -> Result<#ty, ::converse::error::Error> {
+            pub fn server<#auto>(#receiver) -> Result<#ty, ::converse::error::Error> {
 
                 let proc = ::converse::procdir::ProcessDirectory::new(#dir)?;
                 proc.lock()?;
 
-                let socket = ::std::os::unix::net::UnixListener::bind(proc.socket())?;
+                /* we hold the lock, so any socket left here belongs to a dead server */
+                if proc.socket().exists() {
+                    ::std::fs::remove_file(proc.socket())?;
+                }
+
+                let socket: Box<dyn ::converse::transport::Listener> =
+                    Box::new(::converse::transport::unix::UnixListener::bind(proc.socket())?);
+                let proc = Some(proc);
+
+                Ok(#server)
+            }
+
+            /*
+             * adopt the socket passed in by a service manager (LISTEN_FDS),
+             * binding our own as server() does when started by hand
+             */
+            pub fn server_activated<#auto>(#receiver) -> Result<#ty, ::converse::error::Error> {
+
+                let socket = match ::converse::transport::activation::listener()? {
+                    Some(x) => x,
+                    None => return Self::server(#state),
+                };
+
+                /* the socket file belongs to the service manager, leave it be on exit */
+                let proc = None;
+
+                Ok(#server)
+            }
+
+            /* no process directory, the socket goes away with the process */
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn server_abstract<#auto>(#receiver) -> Result<#ty, ::converse::error::Error> {
+
+                let proc = None;
+                let socket: Box<dyn ::converse::transport::Listener> =
+                    Box::new(::converse::transport::unix::UnixListener::bind_abstract(#name)?);
+
+                Ok(#server)
+            }
+
+            /* serve the parent process over stdin/stdout until it hangs up */
+            pub fn serve_stdio(#receiver) -> Result<(), ::converse::error::Error> {
+
+                let proc = None;
+                let socket: Box<dyn ::converse::transport::Listener> =
+                    Box::new(::converse::transport::stdio::StdioListener::new()?);
+
+                let mut server: #concrete = #server;
+                server.run()
+            }
+
+            /* serve over any transport, e.g. tcp or tls */
+            pub fn server_with<#with>(#receiver, listener: L) -> Result<#ty, ::converse::error::Error> {
+
+                let proc = None;
+                let socket: Box<dyn ::converse::transport::Listener> = Box::new(listener);
 
                 Ok(#server)
             }
         };
 
-        self.structure.implement_parent(body)
+        match self.definition {
+            true => self.structure.implement(body),
+            false => self.structure.implement_parent(body),
+        }
     }
 
     fn implementations(&self) -> TokenStream {
@@ -86,71 +178,602 @@ impl Server {
 
     fn core(&self) -> TokenStream {
 
-        let matches = self.handle_arms();
+        let state_ty = &self.state;
+        let part = self.part();
+        let parts = self.parts.iter().map(|x| {
+            let ident = syn::Ident::new(&format!("converse_part_{}", x), x.span());
+            quote! { registry.register(<#state_ty>::#ident())?; }
+        });
 
         quote! {
+            /*
+             * Serve one connection at a time, requests in the order they
+             * arrive. A connection is served until the client hangs up, so a
+             * client holding one open, multiplexed or in a transaction, keeps
+             * every other client waiting. Serve those with run_concurrent.
+             * Connections have no read timeout, over tcp or tls a client that
+             * stops sending without hanging up holds the server as well.
+             */
             pub fn run(&mut self) -> Result<(), ::converse::error::Error> {
 
-                let dir = self.proc.path().clone();
-                ::converse::ctrlc::set_handler(move || {
-                    if dir.exists() {
-                        ::std::fs::remove_dir_all(dir.clone())
-                            .expect("failed to remove server process directory");
-                        ::std::process::exit(0);
-                    }
-                }).expect("Failed to set interrupt handler for server");
+                self.interrupt_handler()?;
 
                 loop {
-                    let (stream, _) = self.socket.accept()?;
-                    self.handle(stream)?;
+                    let (stream, peer) = match Self::next_connection(&self.socket)? {
+                        Some(x) => x,
+                        None => return Ok(()),
+                    };
+
+                    /* clients come and go (and time out), only the listener failing is fatal */
+                    Self::handle(&self.shared, stream, peer).ok();
                 }
             }
 
-            fn handle(&mut self, mut stream: ::std::os::unix::net::UnixStream) -> Result<(), ::converse::error::Error> {
+            /*
+             * Serve every connection on its own thread and run the requests of
+             * a connection concurrently, answering each as soon as it is done.
+             * Connections over transports that can't be split are served
+             * sequentially.
+             */
+            pub fn run_concurrent(&mut self) -> Result<(), ::converse::error::Error>
+            where
+                /* higher ranked like rollback's, a state that isn't Sync can still run() */
+                for<'converse> #state_ty: Send + Sync
+            {
+                self.interrupt_handler()?;
+
+                let (shared, socket) = (&self.shared, &self.socket);
+
+                ::std::thread::scope(|scope| loop {
+                    let incoming = match socket.incoming()? {
+                        Some(x) => x,
+                        None => return Ok(()),
+                    };
+
+                    /* set up on its own thread, a client failing to authenticate only ends that */
+                    scope.spawn(move || incoming()
+                        .and_then(|(stream, peer)| Self::multiplex(shared, stream, peer))
+                        .ok());
+                })
+            }
+
+            /*
+             * copy the state when a transaction begins, so aborting it or the
+             * client hanging up restores the state as it was
+             */
+            pub fn rollback(mut self) -> Self
+            where
+                /* higher ranked so a state that isn't Clone only loses this method */
+                for<'converse> #state_ty: Clone
+            {
+                self.shared.snapshot = Some(<#state_ty as Clone>::clone);
+                self
+            }
+
+            /* what a panicking method does to the server, see converse::server::PanicPolicy */
+            pub fn on_panic(mut self, policy: ::converse::server::PanicPolicy) -> Self {
+                self.shared.panics = policy;
+                self
+            }
+
+            /*
+             * how long a client may take between the calls of a transaction
+             * before it is hung up on and the transaction aborted, None to
+             * wait forever. Transports that can't time out always wait.
+             */
+            pub fn transaction_timeout(mut self, timeout: Option<::std::time::Duration>) -> Self {
+                /* sockets take a zero timeout to be an error */
+                self.shared.transaction_timeout = timeout.map(|x| x.max(::std::time::Duration::from_millis(1)));
+                self
+            }
+
+            /* calls of one multiplexed connection run_concurrent runs at once, see converse::server::Slots */
+            pub fn max_calls(mut self, calls: usize) -> Self {
+                self.shared.calls = calls;
+                self
+            }
+
+            /*
+             * detach from the terminal before run, see converse::daemon.
+             * Only returns in the daemon, which takes over the lockfile.
+             */
+            pub fn daemonize<P: AsRef<::std::path::Path>>(self, log: P) -> Result<Self, ::converse::error::Error> {
 
-                let req = ::converse::protocol::IPCRequest::read(&mut stream)?;
+                let daemon = ::converse::daemon::daemonize(log)?;
 
-                match req.key {
-                    0u32 => {
-                        self.exit();
+                let locked = match self.shared.proc {
+                    Some(ref proc) => proc.relock(),
+                    None => Ok(()),
+                };
+
+                match locked {
+                    Ok(()) => {
+                        daemon.ready();
+                        Ok(self)
                     },
-                    #matches
-                    _ => {
-                        return Err(::converse::error::Error::Server(format!("Invalid function called")));
+                    Err(e) => {
+                        daemon.failed(&e);
+                        Err(e)
                     },
                 }
+            }
+
+            fn interrupt_handler(&self) -> Result<(), ::converse::error::Error> {
+                match self.shared.proc {
+                    Some(ref proc) => proc.remove_on_interrupt(),
+                    None => Ok(()),
+                }
+            }
+
+            fn next_connection(socket: &Box<dyn ::converse::transport::Listener>)
+                -> Result<Option<(Box<dyn ::converse::transport::Stream>, ::converse::transport::Peer)>, ::converse::error::Error>
+            {
+                loop {
+                    match socket.accept() {
+                        /* a client failing to authenticate should not take the server down */
+                        Err(::converse::error::Error::Handshake(_)) => continue,
+                        x => return x,
+                    }
+                }
+            }
+
+            /* serve requests on a connection one by one until the client hangs up */
+            fn handle(
+                shared: &::converse::server::Shared<#state_ty>,
+                mut stream: Box<dyn ::converse::transport::Stream>,
+                peer: ::converse::transport::Peer,
+            ) -> Result<(), ::converse::error::Error> {
+
+                let mut transaction = None;
+                let hang_up = stream.hang_up();
+
+                while let Some(req) = Self::request(&mut stream)? {
+
+                    /* remote clients get an invalid function error instead */
+                    if req.key == 0u32 && peer.is_local() {
+                        shared.exit();
+                    }
+
+                    /* the call it refers to has finished already */
+                    if req.key == ::converse::protocol::CANCEL {
+                        continue;
+                    }
+
+                    let cancel = Self::cancel_token(shared, &req, &hang_up);
+                    let open = transaction.is_some();
+
+                    if let Some(response) = Self::session(shared, &mut transaction, &req, &peer, cancel) {
+                        response.write(&mut stream)?;
+                        ::std::io::Write::flush(&mut stream)?;
+                    }
+
+                    /* a client sitting on the state mustn't stall everyone else */
+                    if transaction.is_some() != open {
+                        stream.set_read_timeout(transaction.as_ref().and(shared.transaction_timeout))?;
+                    }
+                }
+
+                Ok(())
+            }
+
+            /*
+             * like handle, but each request runs on its own thread, up to
+             * shared.calls at once. Requests in a transaction run in order
+             * on the reading thread instead.
+             */
+            fn multiplex(
+                shared: &::converse::server::Shared<#state_ty>,
+                stream: Box<dyn ::converse::transport::Stream>,
+                peer: ::converse::transport::Peer,
+            ) -> Result<(), ::converse::error::Error>
+            where
+                for<'converse> #state_ty: Send + Sync
+            {
+                let (mut reader, writer) = match stream.split() {
+                    Ok(x) => x,
+                    Err(stream) => return Self::handle(shared, stream, peer),
+                };
+
+                let (writer, peer) = (&::std::sync::Mutex::new(writer), &peer);
+
+                /* calls still running, by request id */
+                let calls = &::std::sync::Mutex::new(::std::collections::HashMap::new());
+                let slots = &::converse::server::Slots::new(shared.calls);
+
+                ::std::thread::scope(|scope| {
+
+                    let mut transaction = None;
+
+                    loop {
+                        let req = match Self::request(&mut reader) {
+                            Ok(Some(x)) => x,
+                            /* the client is gone, nobody will read the results */
+                            x => {
+                                if let Ok(calls) = calls.lock() {
+                                    calls.values().for_each(::converse::context::CancelToken::cancel);
+                                }
+                                return x.map(|_| ());
+                            },
+                        };
+
+                        if req.key == 0u32 && peer.is_local() {
+                            shared.exit();
+                        }
+
+                        if req.key == ::converse::protocol::CANCEL {
+                            if let Some(call) = calls.lock().ok().as_ref().and_then(|x| x.get(&req.id)) {
+                                call.cancel();
+                            }
+                            continue;
+                        }
+
+                        let cancel = ::converse::context::CancelToken::new();
+
+                        if transaction.is_some() || req.key == ::converse::protocol::BEGIN {
+                            let open = transaction.is_some();
+
+                            if let Some(response) = Self::session(shared, &mut transaction, &req, peer, cancel) {
+                                Self::reply(writer, response)?;
+                            }
+
+                            if transaction.is_some() != open {
+                                reader.set_read_timeout(transaction.as_ref().and(shared.transaction_timeout))?;
+                            }
+                            continue;
+                        }
+
+                        /* oneway calls can't be cancelled, their ids needn't be unique */
+                        if !shared.registry.is_oneway(req.key) {
+                            calls.lock().map(|mut x| x.insert(req.id, cancel.clone())).ok();
+                        }
+
+                        let slot = slots.take();
+
+                        scope.spawn(move || {
+                            let _slot = slot;
+                            let response = Self::respond(shared, &req, peer, cancel, || Self::dispatch(shared, &req));
+                            calls.lock().map(|mut x| x.remove(&req.id)).ok();
+
+                            match response {
+                                Some(response) => Self::reply(writer, response),
+                                None => Ok(()),
+                            }
+                        });
+                    }
+                })
+            }
+
+            fn reply(
+                writer: &::std::sync::Mutex<::converse::transport::WriteHalf>,
+                response: ::converse::protocol::IPCResponse,
+            ) -> Result<(), ::converse::error::Error> {
+
+                let mut writer = writer.lock().map_err(|_|
+                    ::converse::error::Error::Server(format!("Connection writer poisoned")))?;
+
+                response.write(&mut *writer)?;
+                ::std::io::Write::flush(&mut *writer)?;
 
                 Ok(())
             }
 
-            fn exit(&mut self) {
-                self.proc.close();
-                ::std::process::exit(0);
+            /* run a request in or outside of the transaction the connection has open */
+            fn session<'s>(
+                shared: &'s ::converse::server::Shared<#state_ty>,
+                transaction: &mut Option<::converse::transaction::Transaction<'s, #state_ty>>,
+                req: &::converse::protocol::IPCRequest,
+                peer: &::converse::transport::Peer,
+                cancel: ::converse::context::CancelToken,
+            ) -> Option<::converse::protocol::IPCResponse> {
+
+                let none = || ::converse::error::Error::Server(format!("No transaction in progress"));
+
+                let result = match req.key {
+                    ::converse::protocol::BEGIN if transaction.is_some() =>
+                        Err(::converse::error::Error::Server(format!("Transaction already in progress"))),
+                    ::converse::protocol::BEGIN =>
+                        ::converse::transaction::Transaction::begin(shared)
+                            .map(|x| { *transaction = Some(x); }),
+                    ::converse::protocol::COMMIT =>
+                        transaction.take().ok_or_else(none).map(|x| x.commit()),
+                    ::converse::protocol::ABORT =>
+                        transaction.take().ok_or_else(none).map(|x| x.abort()),
+                    _ => return match transaction {
+                        Some(ref mut x) => Self::respond(shared, req, peer, cancel, || Self::dispatch_locked(&shared.registry, x.state(), req)),
+                        None => Self::respond(shared, req, peer, cancel, || Self::dispatch(shared, req)),
+                    },
+                };
+
+                let mut response = match result {
+                    Ok(()) => ::converse::protocol::IPCResponse::ok(vec![]),
+                    Err(e) => ::converse::protocol::IPCResponse::error(&e),
+                };
+
+                response.id = req.id;
+                Some(response)
+            }
+
+            /* next request on a connection, None once the client hung up */
+            fn request<R: ::std::io::Read>(stream: &mut R) -> Result<Option<::converse::protocol::IPCRequest>, ::converse::error::Error> {
+                match ::converse::protocol::IPCRequest::read(stream) {
+                    Ok(x) => Ok(Some(x)),
+                    Err(::converse::error::Error::IOError(ref e))
+                        if e.kind() == ::std::io::ErrorKind::UnexpectedEof => Ok(None),
+                    Err(e) => Err(e),
+                }
+            }
+
+            /* oneway callers hang up right after sending, which doesn't cancel anything */
+            fn cancel_token(
+                shared: &::converse::server::Shared<#state_ty>,
+                req: &::converse::protocol::IPCRequest,
+                hang_up: &Option<::converse::transport::HangUp>,
+            ) -> ::converse::context::CancelToken {
+
+                let cancel = ::converse::context::CancelToken::new();
+
+                match *hang_up {
+                    Some(ref x) if !shared.registry.is_oneway(req.key) => cancel.watch(x.clone()),
+                    _ => cancel,
+                }
+            }
+
+            /* run a request with `dispatch`, None if the caller isn't waiting for a response */
+            fn respond<F>(
+                shared: &::converse::server::Shared<#state_ty>,
+                req: &::converse::protocol::IPCRequest,
+                peer: &::converse::transport::Peer,
+                cancel: ::converse::context::CancelToken,
+                dispatch: F,
+            ) -> Option<::converse::protocol::IPCResponse>
+            where
+                F: FnOnce() -> Result<Vec<u8>, ::converse::error::Error>
+            {
+                let context = ::converse::context::Context::new(peer.clone())
+                    .with_deadline(req.deadline)
+                    .with_cancel(cancel);
+
+                /* nobody is waiting for the result anymore */
+                let mut response = if context.expired() {
+                    ::converse::protocol::IPCResponse::timeout()
+                } else {
+                    let result = shared.check().and_then(|_| ::converse::context::scope(context, || {
+                        /* a panic ends this call only, what else it affects is up to the policy */
+                        ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(dispatch))
+                            .unwrap_or_else(|x| Err(shared.panicked(x)))
+                    }));
+
+                    match result {
+                        Ok(data) => ::converse::protocol::IPCResponse::ok(data),
+                        Err(e) => ::converse::protocol::IPCResponse::error(&e),
+                    }
+                };
+
+                /* oneway callers aren't listening */
+                if shared.registry.is_oneway(req.key) {
+                    return None;
+                }
+
+                response.id = req.id;
+                Some(response)
+            }
+
+            /* call the method `req` refers to and serialize its result */
+            fn dispatch(shared: &::converse::server::Shared<#state_ty>, req: &::converse::protocol::IPCRequest) -> Result<Vec<u8>, ::converse::error::Error> {
+
+                if req.key == ::converse::protocol::BATCH {
+                    let mut state = shared.write()?;
+                    return Self::dispatch_locked(&shared.registry, &mut *state, req);
+                }
+
+                shared.registry.dispatch(shared, req)
+            }
+
+            /*
+             * run the calls of a BATCH request in order, stopping at the first
+             * error. Batches don't nest, a batch in a batch would recurse as
+             * deep as the client likes, and can't open or close transactions.
+             */
+            fn batch(
+                registry: &::converse::registry::Registry<#state_ty>,
+                state: &mut #state_ty,
+                req: &::converse::protocol::IPCRequest,
+            ) -> Result<Vec<u8>, ::converse::error::Error> {
+
+                let mut responses = vec![];
+
+                for req in req.unpack()? {
+                    let result = match req.key {
+                        ::converse::protocol::BATCH
+                        | ::converse::protocol::BEGIN
+                        | ::converse::protocol::COMMIT
+                        | ::converse::protocol::ABORT
+                        | ::converse::protocol::CANCEL =>
+                            Err(::converse::error::Error::Server(format!("Control call {} can't be batched", req.key))),
+                        _ => registry.dispatch_locked(state, &req),
+                    };
+
+                    match result {
+                        Ok(data) => responses.push(::converse::protocol::IPCResponse::ok(data)),
+                        Err(e) => {
+                            responses.push(::converse::protocol::IPCResponse::error(&e));
+                            break;
+                        },
+                    }
+                }
+
+                ::converse::protocol::IPCResponse::pack(responses)
+            }
+
+            /* like dispatch, with the state already locked by the caller */
+            fn dispatch_locked(
+                registry: &::converse::registry::Registry<#state_ty>,
+                state: &mut #state_ty,
+                req: &::converse::protocol::IPCRequest,
+            ) -> Result<Vec<u8>, ::converse::error::Error> {
+
+                if req.key == ::converse::protocol::BATCH {
+                    return Self::batch(registry, state, req);
+                }
+
+                registry.dispatch_locked(state, req)
+            }
+
+            /* the methods of this block and of its parts, by key */
+            fn registry() -> Result<::converse::registry::Registry<#state_ty>, ::converse::error::Error> {
+
+                let mut registry = ::converse::registry::Registry::new();
+                registry.register(#part)?;
+                #(#parts)*
+
+                Ok(registry)
+            }
+        }
+    }
+
+    /*
+     * For a block that's a part of another's service, a function on the
+     * state type giving its methods to the server's registry.
+     */
+    fn part_fn(&self) -> TokenStream {
+
+        let state_ty = &self.state;
+        let part = self.part();
+        let ident = syn::Ident::new(&format!("converse_part_{}", self.part), proc_macro2::Span::call_site());
+
+        self.structure.implement_parent(quote! {
+            #[doc(hidden)]
+            pub fn #ident() -> ::converse::registry::Part<#state_ty> {
+                #part
+            }
+        })
+    }
+
+    /* converse::registry::Part of the methods of this block */
+    fn part(&self) -> TokenStream {
+
+        let state_ty = &self.state;
+        let name = &self.part;
+        let matches = self.handle_arms(false);
+        let locked = self.handle_arms(true);
+        let oneway = self.oneway();
+
+        let imp = self.structure.implementation();
+        let methods = imp.methods().iter()
+            .flat_map(|x| x.instances().iter())
+            .map(|x| {
+                let (name, key) = (&x.name, x.key);
+                quote! { (#name, #key) }
+            });
+
+        let arg = quote! {
+            let arg = |i: usize| req.argv.get(i)
+                .map(|x| &x.data[..])
+                .ok_or_else(|| ::converse::error::Error::Server(format!("Missing argument {}", i)));
+        };
+
+        quote! {
+            {
+                #[allow(unused_variables)]
+                let dispatch = |shared: &::converse::server::Shared<#state_ty>, req: &::converse::protocol::IPCRequest|
+                    -> Result<Vec<u8>, ::converse::error::Error>
+                {
+                    #[allow(unused_variables)]
+                    #arg
+
+                    match req.key {
+                        #matches
+                        _ => Err(::converse::error::Error::Server(format!("Invalid function called"))),
+                    }
+                };
+
+                #[allow(unused_variables)]
+                let locked = |state: &mut #state_ty, req: &::converse::protocol::IPCRequest|
+                    -> Result<Vec<u8>, ::converse::error::Error>
+                {
+                    #[allow(unused_variables)]
+                    #arg
+
+                    match req.key {
+                        #locked
+                        _ => Err(::converse::error::Error::Server(format!("Invalid function called"))),
+                    }
+                };
+
+                ::converse::registry::Part::new(#name, &[#(#methods),*], &#oneway, dispatch, locked)
             }
         }
     }
 
-    fn handle_arms(&self) -> TokenStream {
+    /* match arms for dispatch, or for dispatch_locked if `locked` */
+    fn handle_arms(&self, locked: bool) -> TokenStream {
 
+        let codec = &self.codec;
         let imp = self.structure.implementation();
-        let arms = imp.methods().iter().enumerate().map(|(i,x)| {
+        let instances = imp.methods().iter()
+            .flat_map(|x| x.instances().iter().map(move |instance| (x, instance)));
+
+        let arms = instances.map(|(x, instance)| {
+
+            let idx = instance.key;
+
+            let mut decode = quote!();
+            let mut args = syn::punctuated::Punctuated::new();
 
-            let idx = i as u32 + 1;
+            for (i, ty) in x.arg_types(instance).into_iter().enumerate() {
 
-            let args = (0..x.args().len())
-                .map(|i| quote! {
-                    ::converse::serde_cbor::from_slice(&req.argv[#i].data)?
-                }).collect();
+                let var = syn::Ident::new(&format!("arg{}", i), proc_macro2::Span::call_site());
+
+                /*
+                 * Borrowed arguments can't be decoded as they are, decode
+                 * what they're borrowed from, e.g. a String for &str, and
+                 * lend it to the method.
+                 */
+                match ty {
+                    syn::Type::Reference(ref x) if x.mutability.is_some() => {
+                        let elem = &x.elem;
+                        decode.extend(quote! {
+                            let mut #var: <#elem as ::std::borrow::ToOwned>::Owned = #codec::from_slice(arg(#i)?)?;
+                        });
+                        args.push(quote! { ::std::borrow::BorrowMut::borrow_mut(&mut #var) });
+                    },
+                    syn::Type::Reference(ref x) => {
+                        let elem = &x.elem;
+                        decode.extend(quote! {
+                            let #var: <#elem as ::std::borrow::ToOwned>::Owned = #codec::from_slice(arg(#i)?)?;
+                        });
+                        args.push(quote! { ::std::borrow::Borrow::borrow(&#var) });
+                    },
+                    _ => {
+                        decode.extend(quote! {
+                            let #var = #codec::from_slice(arg(#i)?)?;
+                        });
+                        args.push(quote! { #var });
+                    },
+                }
+            }
 
             let ident = x.ident();
 
-            let call = x.call(None, args);
-            let ret = quote! { let ret = #call; };
+            let ret = if x.is_static() {
+                let call = x.call(quote!(), Some(instance), args);
+                quote! { let ret = #call; }
+            } else if locked {
+                let call = x.call(quote! { state }, Some(instance), args);
+                quote! { let ret = #call; }
+            } else if x.is_mut() {
+                let call = x.call(quote! { state }, Some(instance), args);
+                quote! { let ret = { let mut state = shared.write()?; #call }; }
+            } else {
+                let call = x.call(quote! { state }, Some(instance), args);
+                quote! { let ret = { let state = shared.read()?; #call }; }
+            };
 
             quote_spanned! { ident.span()=>
                 #idx => {
+                    #decode
                     #ret
-                    ::converse::protocol::IPCBuffer::new(::converse::serde_cbor::to_vec(&ret)?).write(&mut stream)?;
+                    Ok(#codec::to_vec(&ret)?)
                 }
             }
 
@@ -160,15 +783,33 @@ impl Server {
     }
 
 
+    /* [u32; N] of the keys of #[converse(oneway)] methods */
+    fn oneway(&self) -> TokenStream {
+
+        let imp = self.structure.implementation();
+        let keys = imp.methods().iter()
+            .filter(|x| x.attrs().oneway)
+            .flat_map(|x| x.instances().iter().map(|x| x.key));
+
+        quote! { [#(#keys),*] }
+    }
+
     fn endpoints(&self) -> TokenStream {
 
         let imp = self.structure.implementation();
         imp.methods().iter().map(|x| {
 
             let args = x.args().iter().map(|x| quote! { #x }).collect();
-            let call = x.call(Some(quote! { state . }), args);
+            /* a panicked call doesn't stop the owner from using the state */
+            let state = if x.is_mut() {
+                quote! { self.shared.state.write().unwrap_or_else(::std::sync::PoisonError::into_inner) }
+            } else {
+                quote! { self.shared.state.read().unwrap_or_else(::std::sync::PoisonError::into_inner) }
+            };
+
+            let call = x.call(state, None, args);
 
-            x.decl(x.ret(), call)
+            x.decl(x.ret(), quote!(), call)
 
         }).collect()
     }
@@ -3,12 +3,14 @@ use quote::quote;
 use proc_macro2::TokenStream;
 
 use syn::{
-    FnArg, GenericParam, LifetimeDef,
+    Attribute, FnArg, GenericParam, Lifetime, LifetimeDef,
     Ident, ImplItem, ImplItemMethod, ItemImpl, Pat,
-    ReturnType, Type, WhereClause
+    ReturnType, Token, Type, Visibility, WhereClause
 };
-use syn::token::{Eq, Add, Comma};
-use syn::punctuated::{Pair, Punctuated};
+use syn::parse::{ParseStream, Parser};
+use syn::token::{Add, Comma};
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
 
 pub struct Structure {
     ident: Ident,
@@ -43,6 +45,10 @@ impl Structure {
         self.members.push(ty);
     }
 
+    pub fn ident(&self) -> &Ident {
+        &self.ident
+    }
+
     pub fn generics(&self) -> &PhantomGenerics {
         &self.generics
     }
@@ -72,6 +78,15 @@ impl Structure {
         quote! { #ident < #gen_params > }
     }
 
+    /* ty with () for the types standing in for lifetimes, where nothing else picks them */
+    pub fn concrete_ty(&self) -> TokenStream {
+
+        let ident = &self.ident;
+        let gen_params = self.generics.concrete_params();
+
+        quote! { #ident < #gen_params > }
+    }
+
     pub fn declare(&self) -> TokenStream {
 
         let ident = &self.ident;
@@ -85,9 +100,10 @@ impl Structure {
             }).collect();
 
         let members = &self.members;
+        let where_clause = &self.generics.where_clause;
 
         quote! {
-            pub struct #ident < #gen_decls > {
+            pub struct #ident < #gen_decls > #where_clause {
                 #markers
                 #members
             }
@@ -149,12 +165,19 @@ pub struct Implementation {
 impl Implementation {
     fn from_impl<'a>(imp: &'a ItemImpl) -> Self {
 
-        let methods = imp.items.iter()
+        let mut methods: Vec<Method> = imp.items.iter()
             .filter_map(|x| match x {
                 ImplItem::Method(x) => Some(x.clone()),
                 _ => None,
             })
-            .map(|x| Method::new(imp.self_ty.clone(), x)).collect();
+            .map(|x| Method::new(imp.self_ty.clone(), x))
+            .filter(|x| x.attrs.exported(&x.method))
+            .collect();
+
+        /* a key for every instantiation, see Instance::new */
+        for method in methods.iter_mut() {
+            method.instances = Instance::all(&method.method, &method.attrs);
+        }
 
         Implementation {
             methods: methods,
@@ -170,18 +193,28 @@ impl Implementation {
 pub struct Method {
     ty: Box<Type>,
     method: ImplItemMethod,
+    attrs: MethodAttrs,
+    args: Vec<(Ident, Type)>,
+    instances: Vec<Instance>,
 }
 
 impl Method {
     fn new(ty: Box<Type>, method: ImplItemMethod) -> Self {
         Method {
+            attrs: MethodAttrs::from_attrs(&method.attrs).unwrap_or_default(),
+            args: named_args(&method),
+            instances: vec![],
             ty: ty,
             method: method,
         }
     }
 
+    pub fn attrs(&self) -> &MethodAttrs {
+        &self.attrs
+    }
+
     /* Check if this is an instance method */
-    fn is_static(&self) -> bool {
+    pub fn is_static(&self) -> bool {
         if let Some(pair) = self.method.sig.decl.inputs.first() {
             match pair.value() {
                 FnArg::SelfRef(_) => false,
@@ -193,32 +226,33 @@ impl Method {
         }
     }
 
-    /* Get a list of arguments to the function - ignore self */
-    pub fn args(&self) -> Punctuated<Pat, Comma> {
-        self.method.sig.decl.inputs.pairs()
-            .filter(|x| match x.value() {
-                FnArg::SelfRef(_) => false,
-                FnArg::SelfValue(_) => false,
-                _ => true,
-            })
-            .filter_map(|x| {
-                match x.value() {
-                    FnArg::Captured(arg) => Some(Pair::new(
-                        arg.pat.clone(),
-                        x.punct().map(|x| (**x).clone())
-                    )),
-                    FnArg::Inferred(x) => {
-                        eprintln!("warning: ingored inferred variable {}", quote!(#x));
-                        None
-                    },
-                    FnArg::Ignored(x) => {
-                        eprintln!("warning: ingored variable {}", quote!(#x));
-                        None
-                    },
-                    _ => None,
-                }
-            })
-            .collect()
+    /* Check if the method needs exclusive access to self */
+    pub fn is_mut(&self) -> bool {
+        match self.method.sig.decl.inputs.first().map(|x| x.into_value()) {
+            Some(FnArg::SelfRef(x)) => x.mutability.is_some(),
+            Some(FnArg::SelfValue(_)) => true,
+            _ => false,
+        }
+    }
+
+    /* Names of the arguments, ignoring self, see named_args */
+    pub fn args(&self) -> Punctuated<Ident, Comma> {
+        self.args.iter().map(|(x, _)| x.clone()).collect()
+    }
+
+    /* Types of the arguments in `instance`, in the order of args */
+    pub fn arg_types(&self, instance: &Instance) -> Vec<Type> {
+        self.args.iter().map(|(_, x)| instance.substitute(x)).collect()
+    }
+
+    /* One per #[converse(instantiate(...))], or just one if not generic */
+    pub fn instances(&self) -> &Vec<Instance> {
+        &self.instances
+    }
+
+    /* The method's own type parameters */
+    pub fn type_params(&self) -> Vec<&Ident> {
+        self.method.sig.decl.generics.type_params().map(|x| &x.ident).collect()
     }
 
     pub fn ident(&self) -> &Ident {
@@ -233,18 +267,74 @@ impl Method {
         }
     }
 
-    /* Create a function declaration stream */
-    pub fn decl(&self, ret: TokenStream, body: TokenStream) -> TokenStream {
+    /* Check if the method returns some Result, its error can carry ours */
+    pub fn returns_result(&self) -> bool {
+        match self.method.sig.decl.output {
+            ReturnType::Type(_, ref x) => match **x {
+                Type::Path(ref x) if x.qself.is_none() => x.path.segments.last().map_or(false, |x| x.value().ident == "Result"),
+                _ => false,
+            },
+            ReturnType::Default => false,
+        }
+    }
+
+    /* Create a function declaration stream, `bounds` are added to its where clause */
+    pub fn decl(&self, ret: TokenStream, bounds: TokenStream, body: TokenStream) -> TokenStream {
+        self.declare(self.vis(), self.inputs(), ret, bounds, body)
+    }
+
+    /*
+     * The method's own visibility, or pub for a private one exported with
+     * #[converse(expose)], which would be out of reach in a generated module
+     */
+    fn vis(&self) -> TokenStream {
+        match self.method.vis {
+            Visibility::Inherited => quote! { pub },
+            ref x => quote! { #x },
+        }
+    }
+
+    /* Like decl with the original return type and no visibility, for implementing a trait */
+    pub fn decl_trait(&self, body: TokenStream) -> TokenStream {
+        self.declare(quote!(), self.inputs(), self.ret(), quote!(), body)
+    }
+
+    /* self and the named args */
+    fn inputs(&self) -> TokenStream {
+        let receiver = self.method.sig.decl.inputs.iter()
+            .filter(|x| match x {
+                FnArg::SelfRef(_) => true,
+                FnArg::SelfValue(_) => true,
+                _ => false,
+            });
+
+        let args = self.args.iter().map(|(x, ty)| quote! { #x: #ty });
+
+        quote! { #(#receiver,)* #(#args),* }
+    }
+
+    /* Like decl, but taking `receiver` in place of the original self argument */
+    pub fn decl_on(&self, receiver: TokenStream, ret: TokenStream, bounds: TokenStream, body: TokenStream) -> TokenStream {
+
+        let args = self.args.iter().map(|(x, ty)| quote! { #x: #ty });
+
+        self.declare(self.vis(), quote! { #receiver, #(#args),* }, ret, bounds, body)
+    }
+
+    fn declare(&self, vis: TokenStream, inputs: TokenStream, ret: TokenStream, bounds: TokenStream, body: TokenStream) -> TokenStream {
 
         let sig = &self.method.sig;
         let decl = &sig.decl;
 
-        if decl.variadic.is_some() {
-            eprintln!("warning: variadic methods are not supported yet");
-            return quote!();
+        let mut predicates: Vec<TokenStream> = decl.generics.where_clause.iter()
+            .flat_map(|x| x.predicates.iter())
+            .map(|x| quote! { #x })
+            .collect();
+
+        if !bounds.is_empty() {
+            predicates.push(bounds);
         }
 
-        let vis = &self.method.vis;
         let defaultness = &self.method.defaultness;
         let constness = &sig.constness;
         let unsafety = &sig.unsafety;
@@ -252,31 +342,435 @@ impl Method {
         let abi = &sig.abi;
         let ident = &sig.ident;
         let generics = &decl.generics;
-        let inputs = &decl.inputs;
 
         quote! {
             #vis #defaultness #constness #unsafety #asyncness #abi
-            fn #ident #generics ( #inputs ) -> #ret {
+            fn #ident #generics ( #inputs ) -> #ret where #(#predicates),* {
                 #body
             }
         }
     }
 
-    /* Call the function with args */
-    pub fn call(&self, path: Option<TokenStream>, args: Punctuated<TokenStream, Comma>) -> TokenStream {
+    /*
+     * Call the function on receiver with args, static methods ignore the
+     * receiver. Generic ones are called with the types of `instance`.
+     */
+    pub fn call(&self, receiver: TokenStream, instance: Option<&Instance>, args: Punctuated<TokenStream, Comma>) -> TokenStream {
 
         let ident = &self.method.sig.ident;
 
+        let turbofish = match instance {
+            Some(x) if !x.types.is_empty() => {
+                let types = x.types();
+                quote! { ::<#(#types),*> }
+            },
+            _ => quote!(),
+        };
+
         if self.is_static() {
             let ty = &self.ty;
-            quote! { #ty :: #path #ident(#args) }
+            quote! { #ty :: #ident #turbofish (#args) }
+        } else {
+            quote! { #receiver . #ident #turbofish (#args) }
+        }
+    }
+}
+
+/* A method with its type parameters given, the key it's called by */
+#[derive(Clone)]
+pub struct Instance {
+    pub key: u32,
+    /* put, or put<String> */
+    pub name: String,
+    /* in the order the method declares them */
+    types: Vec<(Ident, Type)>,
+}
+
+impl Instance {
+    /*
+     * The key is a hash of the name, so it doesn't depend on where the
+     * method is declared and impl blocks of one service can't collide by
+     * position, see converse::registry.
+     */
+    fn new(method: &ImplItemMethod, mut types: Vec<(Ident, Type)>) -> Self {
+
+        let order: Vec<&Ident> = method.sig.decl.generics.type_params().map(|x| &x.ident).collect();
+        types.sort_by_key(|(x, _)| order.iter().position(|y| *y == x));
+
+        let ident = &method.sig.ident;
+        let name = match types.is_empty() {
+            true => ident.to_string(),
+            false => {
+                let types = types.iter().map(|(_, x)| x);
+                quote!(#ident<#(#types),*>).to_string().replace(' ', "")
+            },
+        };
+
+        Instance {
+            key: key(&name),
+            name: name,
+            types: types,
+        }
+    }
+
+    /* one for each instantiation of `method`, or the one of a method that isn't generic */
+    fn all(method: &ImplItemMethod, attrs: &MethodAttrs) -> Vec<Self> {
+
+        let mut instances = attrs.instantiate.clone();
+        if instances.is_empty() {
+            instances.push(vec![]);
+        }
+
+        instances.into_iter()
+            .map(|types| Instance::new(method, types))
+            .collect()
+    }
+
+    pub fn types(&self) -> Vec<&Type> {
+        self.types.iter().map(|(_, x)| x).collect()
+    }
+
+    /* `ty` with the type parameters replaced */
+    fn substitute(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        Substitute(&self.types).visit_type_mut(&mut ty);
+        ty
+    }
+}
+
+/*
+ * FNV-1a of `name`, kept clear of 0 (exit) and of the keys the protocol
+ * reserves at the top of the range.
+ */
+fn key(name: &str) -> u32 {
+
+    let mut key = 0x811c_9dc5u32;
+    let mut bytes = name.as_bytes().to_vec();
+
+    loop {
+        for byte in bytes.iter() {
+            key ^= u32::from(*byte);
+            key = key.wrapping_mul(0x0100_0193);
+        }
+
+        if key != 0 && key < u32::MAX - 0xff {
+            return key;
+        }
+
+        bytes = vec![b'\''];
+    }
+}
+
+struct Substitute<'a>(&'a [(Ident, Type)]);
+
+impl<'a> VisitMut for Substitute<'a> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+
+        let param = match ty {
+            Type::Path(x) if x.qself.is_none() && x.path.leading_colon.is_none() && x.path.segments.len() == 1 => {
+                let segment = &x.path.segments[0];
+                match segment.arguments.is_empty() {
+                    true => Some(&segment.ident),
+                    false => None,
+                }
+            },
+            _ => None,
+        };
+
+        match param.and_then(|x| self.0.iter().find(|(p, _)| p == x)) {
+            Some((_, x)) => *ty = x.clone(),
+            None => visit_mut::visit_type_mut(self, ty),
+        }
+    }
+}
+
+/*
+ * Name every argument so the generated methods can pass it on: plain names
+ * are kept, patterns like `(a, b)` or `_` get argN, made unique among the
+ * names kept.
+ */
+fn named_args(method: &ImplItemMethod) -> Vec<(Ident, Type)> {
+
+    let args: Vec<(Option<Ident>, Type)> = method.sig.decl.inputs.iter()
+        .filter_map(|x| match x {
+            FnArg::Captured(arg) => match arg.pat {
+                Pat::Ident(ref x) if x.by_ref.is_none() && x.subpat.is_none() => Some((Some(x.ident.clone()), arg.ty.clone())),
+                _ => Some((None, arg.ty.clone())),
+            },
+            FnArg::Ignored(ty) => Some((None, ty.clone())),
+            /* self, and inferred arguments which are rejected by check */
+            _ => None,
+        })
+        .collect();
+
+    let taken: Vec<String> = args.iter()
+        .filter_map(|(x, _)| x.as_ref().map(Ident::to_string))
+        .collect();
+
+    args.into_iter().enumerate()
+        .map(|(i, (ident, ty))| match ident {
+            Some(x) => (x, ty),
+            None => {
+                let mut name = format!("arg{}", i);
+                while taken.contains(&name) {
+                    name.push('_');
+                }
+
+                (Ident::new(&name, proc_macro2::Span::call_site()), ty)
+            },
+        })
+        .collect()
+}
+
+/* options given to a method with #[converse(...)] */
+#[derive(Clone, Default)]
+pub struct MethodAttrs {
+    /* safe to replay if the connection fails */
+    pub idempotent: bool,
+    /* the client doesn't wait for (and the server doesn't send) a response */
+    pub oneway: bool,
+    /* leave a pub method out of the interface */
+    pub skip: bool,
+    /* put a private method in the interface */
+    pub expose: bool,
+    /* the types to call a generic method with, one key each */
+    pub instantiate: Vec<Vec<(Ident, Type)>>,
+}
+
+impl MethodAttrs {
+    pub fn from_attrs(attrs: &[Attribute]) -> Result<Self, syn::Error> {
+
+        let mut parsed = MethodAttrs::default();
+
+        for attr in attrs.iter().filter(|x| is_converse_attr(x)) {
+
+            let parser = |input: ParseStream| -> syn::Result<()> {
+
+                if !input.peek(syn::token::Paren) {
+                    return Err(syn::Error::new_spanned(attr, "expected #[converse(...)]"));
+                }
+
+                let content;
+                syn::parenthesized!(content in input);
+
+                while !content.is_empty() {
+
+                    let word: Ident = content.parse()?;
+
+                    match word.to_string().as_str() {
+                        "idempotent" => parsed.idempotent = true,
+                        "oneway" => parsed.oneway = true,
+                        "skip" => parsed.skip = true,
+                        "expose" => parsed.expose = true,
+                        "instantiate" => {
+                            let list;
+                            syn::parenthesized!(list in content);
+                            parsed.instantiate.extend(instances(&list)?);
+                        },
+                        _ => return Err(syn::Error::new_spanned(word,
+                            "unknown converse method attribute, expected `idempotent`, `oneway`, `skip`, `expose` or `instantiate`")),
+                    }
+
+                    if !content.is_empty() {
+                        content.parse::<Token![,]>()?;
+                    }
+                }
+
+                Ok(())
+            };
+
+            parser.parse2(attr.tts.clone())?;
+
+            if parsed.skip && parsed.expose {
+                return Err(syn::Error::new_spanned(attr, "a method can't be both skipped and exposed"));
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /* pub methods are part of the interface unless skipped, others only if exposed */
+    pub fn exported(&self, method: &ImplItemMethod) -> bool {
+        match method.vis {
+            Visibility::Inherited => self.expose,
+            _ => !self.skip,
+        }
+    }
+}
+
+/* `V = String, V = u64` or, for several parameters, `(K = u32, V = String), ...` */
+fn instances(input: ParseStream) -> syn::Result<Vec<Vec<(Ident, Type)>>> {
+
+    let assignment = |input: ParseStream| -> syn::Result<(Ident, Type)> {
+        let param: Ident = input.parse()?;
+        input.parse::<Token![=]>()?;
+        Ok((param, input.parse()?))
+    };
+
+    let mut instances = vec![];
+
+    while !input.is_empty() {
+
+        if input.peek(syn::token::Paren) {
+            let group;
+            syn::parenthesized!(group in input);
+
+            let types = group.parse_terminated::<_, Token![,]>(assignment)?;
+            instances.push(types.into_iter().collect());
         } else {
-            quote! { self . #path #ident(#args) }
+            instances.push(vec![assignment(input)?]);
+        }
+
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
         }
     }
+
+    Ok(instances)
 }
 
+/*
+ * Find what the generated client and server can't express, pointing at the
+ * offending method or argument. The rest of the derive assumes these passed.
+ */
+pub fn check(imp: &ItemImpl) -> Vec<syn::Error> {
+
+    let mut errors = vec![];
+    /* every key of the block, by name */
+    let mut keys: Vec<(String, u32)> = vec![];
+
+    let methods = imp.items.iter().filter_map(|x| match x {
+        ImplItem::Method(x) => Some(x),
+        _ => None,
+    });
+
+    for method in methods {
+
+        let decl = &method.sig.decl;
+
+        let attrs = match MethodAttrs::from_attrs(&method.attrs) {
+            Ok(x) => x,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            },
+        };
+
+        /* anything goes for methods the client can't call */
+        if !attrs.exported(method) {
+            continue;
+        }
+
+        if let Some(ref x) = decl.variadic {
+            errors.push(syn::Error::new_spanned(x, "variadic methods can't be called remotely"));
+        }
+
+        for arg in decl.inputs.iter() {
+            match arg {
+                FnArg::Inferred(x) => errors.push(syn::Error::new_spanned(x, "arguments of remote methods need a type")),
+                FnArg::Captured(x) => if let Type::ImplTrait(ref x) = x.ty {
+                    errors.push(syn::Error::new_spanned(x,
+                        "impl Trait arguments can't be called remotely, use a type parameter and #[converse(instantiate(...))]"));
+                },
+                _ => {},
+            }
+        }
+
+        /* unlike types, instantiate(...) can't give them */
+        for gen in decl.generics.params.iter() {
+            if let GenericParam::Const(x) = gen {
+                errors.push(syn::Error::new_spanned(x, "methods with const parameters can't be called remotely"));
+            }
+        }
+
+        /* the server has to know which types to call a generic method with */
+        let params: Vec<&Ident> = decl.generics.type_params().map(|x| &x.ident).collect();
+
+        if !params.is_empty() && attrs.instantiate.is_empty() {
+            errors.push(syn::Error::new_spanned(&decl.generics,
+                "generic methods can only be called remotely with the types given by #[converse(instantiate(...))]"));
+        }
+
+        if params.is_empty() && !attrs.instantiate.is_empty() {
+            errors.push(syn::Error::new_spanned(&method.sig.ident, "only generic methods can be instantiated"));
+        }
+
+        let mut seen = vec![];
+        for types in attrs.instantiate.iter().filter(|_| !params.is_empty()) {
+
+            let span = types.first().map(|(x, _)| x.span()).unwrap_or(method.sig.ident.span());
+
+            for (param, _) in types.iter() {
+                if !params.contains(&param) {
+                    errors.push(syn::Error::new_spanned(param, format!("`{}` isn't a type parameter of this method", param)));
+                }
+            }
+
+            if params.iter().any(|x| types.iter().filter(|(y, _)| y == *x).count() != 1) {
+                errors.push(syn::Error::new(span, "give every type parameter of the method exactly one type"));
+            }
+
+            let mut sorted: Vec<String> = types.iter().map(|(x, ty)| quote!(#x = #ty).to_string()).collect();
+            sorted.sort();
+
+            if seen.contains(&sorted) {
+                errors.push(syn::Error::new(span, "instantiated with the same types twice"));
+            }
+
+            seen.push(sorted);
+        }
+
+        /*
+         * Keys are hashes of the names, different names can still end up
+         * with the same one. Across blocks that's found when the server
+         * registers them, see converse::registry.
+         */
+        for instance in Instance::all(method, &attrs) {
+            let taken = keys.iter().find(|(name, key)| *key == instance.key && *name != instance.name);
+
+            if let Some((name, _)) = taken {
+                errors.push(syn::Error::new_spanned(&method.sig.ident,
+                    format!("`{}` has the same key as `{}`, rename one of them", instance.name, name)));
+            }
+
+            keys.push((instance.name, instance.key));
+        }
+
+        /* nothing comes back to return */
+        if attrs.oneway {
+            match decl.output {
+                ReturnType::Type(_, ref x) if !is_unit(x) => {
+                    errors.push(syn::Error::new_spanned(x, "#[converse(oneway)] methods can't return a value"));
+                },
+                _ => {},
+            }
+        }
+    }
+
+    errors
+}
+
+fn is_unit(ty: &Type) -> bool {
+    match *ty {
+        Type::Tuple(ref x) => x.elems.is_empty(),
+        _ => false,
+    }
+}
+
+/* #[converse(...)] on a method, removed before the impl is emitted */
+pub fn is_converse_attr(attr: &Attribute) -> bool {
+    attr.path.segments.len() == 1 && attr.path.segments[0].ident == "converse"
+}
+
+/*
+ * The generics of the impl, for the generated structs. Type parameters are
+ * kept in PhantomData, lifetimes in a PhantomData<&'a PhantomType_N> over a
+ * type parameter of our own, const parameters need nothing. Defaults are left
+ * out, impl headers can't have them.
+ */
 pub struct PhantomGenerics {
+    lifetimes: Vec<LifetimeDef>,
+    /* types and consts in the order the impl declares them, then ours */
     generics: Vec<PhantomGeneric>,
     where_clause: Option<WhereClause>,
 }
@@ -284,55 +778,46 @@ pub struct PhantomGenerics {
 impl PhantomGenerics {
     fn from_impl<'a>(imp: &'a ItemImpl) -> Self {
 
-        let mut generics = vec![];
         let mut lifetimes = vec![];
-        let mut type_ids = vec![];
+        let mut generics = vec![];
 
-        /* first split types and lifetimes into seperate vectors */
-        /* track ids so we can check for conflicts */
         for gen in imp.generics.params.iter() {
             match gen {
                 GenericParam::Type(x) => {
-
-                    let punct = x.bounds.iter().map(|x| quote!(#x)).collect();
-                    let bounds = PhantomBounds::new(None, punct, x.eq_token.clone(), x.default.clone());
-
-                    type_ids.push(&x.ident);
-                    generics.push(PhantomGeneric::new(x.ident.clone(), bounds));
+                    let bounds = x.bounds.iter().map(|x| quote!(#x)).collect();
+                    generics.push(PhantomGeneric::new(x.ident.clone(), PhantomKind::Type(bounds)));
+                },
+                GenericParam::Const(x) => {
+                    generics.push(PhantomGeneric::new(x.ident.clone(), PhantomKind::Const(x.ty.clone())));
                 },
-                GenericParam::Lifetime(x) => lifetimes.push(x),
-                _ => eprintln!("Constant generic types are currently unsupported for Converse"),
+                GenericParam::Lifetime(x) => lifetimes.push(x.clone()),
             }
         }
 
-        /* autogenerate new types which are valid for `lifetime` lifetime */
-        /* seperate iteration so we have a guarentee for no conflicting types */
+        /* a new type for every lifetime, named so it can't conflict with the impl's */
         let mut ty_idx = 0;
-        while !lifetimes.is_empty() {
+        for lifetime in lifetimes.iter() {
 
-            let lifetime = lifetimes.pop().unwrap();
-            let id = Ident::new(&format!("PhantomType_{}", ty_idx), proc_macro2::Span::call_site());
-
-            /* conflict */
-            if type_ids.contains(&&id) {
+            let id = loop {
+                let id = Ident::new(&format!("PhantomType_{}", ty_idx), proc_macro2::Span::call_site());
                 ty_idx += 1;
-                lifetimes.push(lifetime);
-                continue;
-            }
 
-            let bounds = PhantomBounds::new(Some(lifetime.clone()), Punctuated::new(), None, None);
-            generics.push(PhantomGeneric::new(id, bounds));
-        }
+                if !generics.iter().any(|x| x.ident == id) {
+                    break id;
+                }
+            };
 
-        /* lifetimes first */
-        generics.reverse();
+            generics.push(PhantomGeneric::new(id, PhantomKind::Lifetime(lifetime.lifetime.clone())));
+        }
 
         PhantomGenerics {
+            lifetimes: lifetimes,
             generics: generics,
             where_clause: imp.generics.where_clause.clone(),
         }
     }
 
+    /* the types standing in for lifetimes, for constructors to declare */
     pub fn generated(&self) -> Punctuated<TokenStream, Comma> {
         self.generics.iter()
             .filter(|x| x.is_reference())
@@ -340,72 +825,93 @@ impl PhantomGenerics {
             .collect()
     }
 
-    /* 'a, T: 'a , U: 'b */
-    fn decls(&self) -> Punctuated<TokenStream, Comma> {
-        /* get lifetimes */
-        self.generics.iter()
-            .filter_map(PhantomGeneric::lifetime)
-            .chain(
-                /* get types */
-                self.generics.iter().map(PhantomGeneric::decl)
-            )
+    pub fn where_clause(&self) -> &Option<WhereClause> {
+        &self.where_clause
+    }
+
+    /* 'a: 'b, T: Clone, const N: usize, PhantomType_0: 'a */
+    pub fn decls(&self) -> Punctuated<TokenStream, Comma> {
+        self.lifetimes.iter()
+            .map(|x| quote! { #x })
+            .chain(self.generics.iter().map(PhantomGeneric::decl))
             .collect()
     }
 
-    /* 'a, T */
-    fn params(&self) -> Punctuated<TokenStream, Comma> {
-        self.generics.iter()
-            .filter_map(PhantomGeneric::lifetime)
-            .chain(
-                /* get types */
-                self.generics.iter().map(PhantomGeneric::ident)
-            )
+    /* 'a, T, N, PhantomType_0 */
+    pub fn params(&self) -> Punctuated<TokenStream, Comma> {
+        self.lifetimes.iter()
+            .map(|x| {
+                let lifetime = &x.lifetime;
+                quote! { #lifetime }
+            })
+            .chain(self.generics.iter().map(PhantomGeneric::ident))
+            .collect()
+    }
+
+    /* 'a, T, N, () */
+    pub fn concrete_params(&self) -> Punctuated<TokenStream, Comma> {
+        self.params().into_iter()
+            .zip(self.lifetimes.iter().map(|_| false).chain(self.generics.iter().map(PhantomGeneric::is_reference)))
+            .map(|(x, generated)| match generated {
+                true => quote! { () },
+                false => x,
+            })
             .collect()
     }
 
     /* PhantomData<&'a T>, ... */
     fn markers(&self) -> Vec<TokenStream> {
-        self.generics.iter().map(PhantomGeneric::marker).collect()
+        self.generics.iter().filter_map(PhantomGeneric::marker).collect()
     }
 
-
     fn instances(&self) -> Vec<TokenStream> {
-        self.generics.iter().map(PhantomGeneric::instance).collect()
+        self.generics.iter().filter_map(PhantomGeneric::instance).collect()
     }
 
+    /* how many markers there are */
     fn len(&self) -> usize {
-        self.generics.len()
+        self.markers().len()
     }
 }
 
+enum PhantomKind {
+    /* T: Clone */
+    Type(Punctuated<TokenStream, Add>),
+    /* const N: usize */
+    Const(Type),
+    /* ours, for 'a */
+    Lifetime(Lifetime),
+}
+
 struct PhantomGeneric {
     ident: Ident,
-    bounds: PhantomBounds,
+    kind: PhantomKind,
 }
 
 impl PhantomGeneric {
-    fn new(ident: Ident, bounds: PhantomBounds) -> Self {
+    fn new(ident: Ident, kind: PhantomKind) -> Self {
         PhantomGeneric {
             ident: ident,
-            bounds: bounds,
+            kind: kind,
         }
     }
 
-    /* lifetime is only some when constructed as a reference above */
     fn is_reference(&self) -> bool {
-        self.bounds.lifetime.is_some()
+        match self.kind {
+            PhantomKind::Lifetime(_) => true,
+            _ => false,
+        }
     }
 
-    /* T: 'a */
+    /* T: Clone, const N: usize or PhantomType_0: 'a */
     fn decl(&self) -> TokenStream {
         let ident = &self.ident;
-        let bounds = self.bounds.bounds();
-        quote! { #ident #bounds }
-    }
-
-    /* 'a */
-    fn lifetime(&self) -> Option<TokenStream> {
-        self.bounds.lifetime.as_ref().map(|x| quote! { #x })
+        match self.kind {
+            PhantomKind::Type(ref x) if x.is_empty() => quote! { #ident },
+            PhantomKind::Type(ref x) => quote! { #ident: #x },
+            PhantomKind::Const(ref x) => quote! { const #ident: #x },
+            PhantomKind::Lifetime(ref x) => quote! { #ident: #x },
+        }
     }
 
     /* T */
@@ -414,66 +920,18 @@ impl PhantomGeneric {
         quote! { #ident }
     }
 
-    /* PhantomData<&'a T> */
-    fn marker(&self) -> TokenStream {
-        let ty = self.marker_type();
-        quote! { ::std::marker::PhantomData < #ty > }
-    }
-
-    /* &'a T */
-    fn marker_type(&self) -> TokenStream {
-        if self.bounds.lifetime.is_some() {
-            let lt = self.bounds.lifetime.as_ref().unwrap();
-            let ident = &self.ident;
-            quote! { & #lt #ident }
-        } else {
-            let ident = &self.ident;
-            quote! { #ident }
+    /* PhantomData<T> or PhantomData<&'a PhantomType_0>, nothing for consts */
+    fn marker(&self) -> Option<TokenStream> {
+        let ident = &self.ident;
+        match self.kind {
+            PhantomKind::Type(_) => Some(quote! { ::std::marker::PhantomData < #ident > }),
+            PhantomKind::Const(_) => None,
+            PhantomKind::Lifetime(ref x) => Some(quote! { ::std::marker::PhantomData < & #x #ident > }),
         }
     }
 
     /* PhantomData */
-    fn instance(&self) -> TokenStream {
-        quote! { ::std::marker::PhantomData }
-    }
-}
-
-struct PhantomBounds {
-    lifetime: Option<LifetimeDef>,
-    bounds: Punctuated<TokenStream, Add>,
-    eq_token: Option<Eq>,
-    default: Option<Type>,
-}
-
-impl PhantomBounds {
-    fn new(
-        lifetime: Option<LifetimeDef>,
-        bounds: Punctuated<TokenStream, Add>,
-        eq_token: Option<Eq>,
-        default: Option<Type>,
-    ) -> Self {
-        PhantomBounds {
-            lifetime,
-            bounds,
-            eq_token,
-            default,
-        }
-    }
-
-    fn bounds(&self) -> Option<TokenStream> {
-        if self.lifetime.is_none() && self.bounds.is_empty() {
-            None
-        } else {
-            /* prepend the bounds with the explicit lifetime */
-            let mut punct: Punctuated<TokenStream, Add> = self.lifetime.iter().map(|x| quote! { #x }).collect();
-            punct.extend(self.bounds.clone().into_iter());
-
-            let eq = &self.eq_token;
-            let default = &self.default;
-
-            Some(quote! {
-                : #punct #eq #default
-            })
-        }
+    fn instance(&self) -> Option<TokenStream> {
+        self.marker().map(|_| quote! { ::std::marker::PhantomData })
     }
 }
@@ -0,0 +1,68 @@
+use std::thread;
+
+use converse_derive::Converse;
+use converse::error::Error;
+use converse::serde::Serialize;
+use converse::transport::loopback;
+
+pub struct Buffer<T, const N: usize> {
+    items: Vec<T>,
+}
+
+/* const parameters in any order, the client and server take them in the same */
+#[Converse(buffer)]
+impl<const N: usize, T> Buffer<T, N>
+where
+    T: Clone + Serialize,
+{
+    pub fn push(&mut self, x: u32) -> bool {
+        self.items.len() < N && x > 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+pub struct View<'a, 'b: 'a> {
+    name: &'a str,
+    data: &'b [u8],
+}
+
+/* several lifetimes, one bounded by the other */
+#[Converse(view)]
+impl<'a, 'b: 'a> View<'a, 'b> {
+    pub fn len(&self) -> usize {
+        self.name.len() + self.data.len()
+    }
+}
+
+fn buffer(client: &mut BufferClient<4, String>) -> Result<usize, Error> {
+    assert!(client.push(1)?);
+    assert!(!client.push(0)?);
+    client.capacity()
+}
+
+fn view<'a, 'b: 'a, A, B>(client: &ViewClient<'a, 'b, A, B>) -> Result<usize, Error> {
+    client.len()
+}
+
+fn main() -> Result<(), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Buffer::<String, 4> { items: vec![] }.server_with(listener)?;
+    thread::spawn(move || server.run());
+
+    let mut client = Buffer::<String, 4>::client_with(connector)?;
+    assert_eq!(buffer(&mut client)?, 4);
+
+    let (listener, connector) = loopback::pair();
+    /* the lifetimes' stand-ins are the caller's to pick */
+    let mut server: ViewServer<'_, '_, (), ()> = View { name: "four", data: &[1, 2] }.server_with(listener)?;
+    thread::spawn(move || server.run());
+
+    let client: ViewClient<'_, '_, (), ()> = View::client_with(connector)?;
+    assert_eq!(view(&client)?, 6);
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use converse_derive::Converse;
+
+pub struct Counter {
+    count: u64,
+}
+
+#[Converse(module = "counter")]
+impl Counter {
+    /* private to this module, yet callable on the client in `counter` */
+    #[converse(expose)]
+    fn increment(&mut self) -> u64 {
+        self.count += 1;
+        self.count
+    }
+}
+
+fn _call(client: &mut counter::CounterClient) -> Result<(u64, (u64,), u64), converse::error::Error> {
+    let one = client.increment()?;
+    let batch = client.batch().increment().send()?;
+
+    let mut transaction = client.transaction()?;
+    let two = transaction.increment()?;
+    transaction.commit()?;
+
+    Ok((one, batch, two))
+}
+
+fn main() {}
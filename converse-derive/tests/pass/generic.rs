@@ -0,0 +1,65 @@
+use std::thread;
+
+use converse::error::Error;
+use converse::serde::de::DeserializeOwned;
+use converse::transport::loopback;
+use converse_derive::Converse;
+
+struct Store {
+    items: Vec<String>,
+}
+
+#[Converse(store)]
+impl Store {
+    #[converse(instantiate(V = String, V = u64))]
+    pub fn put<V: ToString>(&mut self, v: &V) -> usize {
+        self.items.push(v.to_string());
+        self.items.len()
+    }
+
+    #[converse(instantiate((K = u32, V = String), (K = String, V = Vec<u8>)))]
+    pub fn get<K, V>(&self, _: K) -> Option<V>
+    where
+        V: DeserializeOwned,
+    {
+        None
+    }
+
+    /* named like the key the client calls by */
+    #[converse(instantiate(K = u32, K = String))]
+    pub fn find<K: ToString>(&self, key: K) -> Option<usize> {
+        self.items.iter().position(|x| *x == key.to_string())
+    }
+
+    pub fn name(&self, key: usize) -> String {
+        self.items[key].clone()
+    }
+}
+
+/* the client's methods stay generic */
+fn _get(client: &StoreClient) -> Result<Option<String>, Error> {
+    client.get(1u32)
+}
+
+fn main() -> Result<(), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Store { items: vec![] }.server_with(listener)?;
+    thread::spawn(move || server.run());
+
+    let mut client = Store::client_with(connector)?;
+
+    assert_eq!(client.put(&"one".to_string())?, 1);
+    assert_eq!(client.put(&2u64)?, 2);
+    assert_eq!(client.get::<u32, String>(0)?, None);
+
+    assert_eq!(client.find(2u32)?, Some(1));
+    assert_eq!(client.find("one".to_string())?, Some(0));
+    assert_eq!(client.find(3u32)?, None);
+    assert_eq!(client.name(1)?, "2");
+
+    let (found, name) = client.batch().find("2".to_string()).name(0).send()?;
+    assert_eq!((found, name), (Some(1), "one".to_string()));
+
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use converse_derive::Converse;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/* can't be shared between threads, so only served one call at a time */
+struct Cache {
+    hits: Rc<RefCell<u64>>,
+}
+
+#[Converse(cache)]
+impl Cache {
+    pub fn hit(&self) -> u64 {
+        *self.hits.borrow_mut() += 1;
+        *self.hits.borrow()
+    }
+}
+
+fn _serve(cache: Cache) -> Result<(), converse::error::Error> {
+    cache.server()?.run()
+}
+
+fn main() {}
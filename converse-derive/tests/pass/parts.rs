@@ -0,0 +1,76 @@
+use std::thread;
+
+use converse::error::Error;
+use converse::transport::loopback;
+use converse_derive::Converse;
+
+pub struct Playlist {
+    list: Vec<String>,
+}
+
+#[Converse(name = "playlist", module = "ipc", parts(edit, order))]
+impl Playlist {
+    pub fn add(&mut self, x: String) {
+        self.list.push(x);
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.list.clone()
+    }
+}
+
+/* parts can live in other modules, giving the module the client is in */
+mod edit {
+    use converse_derive::Converse;
+    use super::{ipc, Playlist};
+
+    #[Converse(part = "edit", module = "ipc")]
+    impl Playlist {
+        pub fn remove(&mut self, i: usize) -> String {
+            self.list.remove(i)
+        }
+    }
+}
+
+mod order {
+    use converse_derive::Converse;
+    use super::{ipc, Playlist};
+
+    pub trait Order {
+        fn reverse(&mut self);
+    }
+
+    #[Converse(part = "order", module = "ipc")]
+    impl Order for Playlist {
+        fn reverse(&mut self) {
+            self.list.reverse();
+        }
+    }
+}
+
+fn call(client: &mut ipc::PlaylistClient) -> Result<Vec<String>, Error> {
+    client.remove(0)?;
+    order::Order::reverse(client);
+    client.list()
+}
+
+fn main() -> Result<(), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Playlist { list: vec![] }.server_with(listener)?;
+    thread::spawn(move || server.run());
+
+    let mut client = Playlist::client_with(connector)?;
+
+    for x in &["a", "b", "c"] {
+        client.add(x.to_string())?;
+    }
+
+    /* every part's methods are served */
+    assert_eq!(call(&mut client)?, vec!["c", "b"]);
+
+    let (removed, list) = client.batch().remove(1).list().send()?;
+    assert_eq!((removed, list), ("b".to_string(), vec!["c".to_string()]));
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::thread;
+
+use converse::error::Error;
+use converse::transport::loopback;
+use converse_derive::Converse;
+
+pub struct Counter {
+    count: u64,
+}
+
+/* parts(..) can come before the key = value arguments */
+#[Converse(parts(reset), module = "counter")]
+impl Counter {
+    pub fn increment(&mut self) -> u64 {
+        self.count += 1;
+        self.count
+    }
+}
+
+#[Converse(part = "reset", module = "counter")]
+impl Counter {
+    pub fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+fn call(client: &mut counter::CounterClient) -> Result<u64, Error> {
+    client.reset()?;
+    client.increment()
+}
+
+fn main() -> Result<(), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Counter { count: 0 }.server_with(listener)?;
+    thread::spawn(move || server.run());
+
+    let mut client = Counter::client_with(connector)?;
+
+    assert_eq!(client.increment()?, 1);
+    assert_eq!(client.increment()?, 2);
+    assert_eq!(call(&mut client)?, 1);
+
+    Ok(())
+}
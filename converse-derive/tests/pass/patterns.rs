@@ -0,0 +1,53 @@
+use std::thread;
+
+use converse::error::Error;
+use converse::transport::loopback;
+use converse_derive::Converse;
+
+struct Playlist {
+    list: Vec<String>,
+}
+
+#[Converse(playlist)]
+impl Playlist {
+    pub fn swap(&mut self, (a, b): (usize, usize)) {
+        self.list.swap(a, b);
+    }
+
+    pub fn slice(&self, [start, end]: [usize; 2], _: bool) -> Vec<String> {
+        self.list[start..end].to_vec()
+    }
+
+    pub fn insert(&mut self, mut i: usize, ref x: String, arg1: u32) {
+        i = i.min(self.list.len() + arg1 as usize);
+        self.list.insert(i, x.clone());
+    }
+
+    /* named like what the client builds its request from */
+    pub fn count(&self, argv: Vec<String>, res: bool, e: u32) -> usize {
+        self.list.iter().filter(|x| argv.contains(x) == res).count() + e as usize
+    }
+}
+
+fn main() -> Result<(), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let playlist = Playlist { list: vec!["a".to_string(), "b".to_string()] };
+    let mut server = playlist.server_with(listener)?;
+    thread::spawn(move || server.run());
+
+    let mut client = Playlist::client_with(connector)?;
+
+    client.swap((0, 1))?;
+    client.insert(1, "c".to_string(), 0)?;
+    assert_eq!(client.slice([0, 3], true)?, vec!["b", "c", "a"]);
+
+    assert_eq!(client.count(vec!["a".to_string(), "c".to_string()], true, 0)?, 2);
+    assert_eq!(client.count(vec!["a".to_string()], false, 1)?, 3);
+
+    let (swapped, count) = client.batch().swap((0, 2)).count(vec!["b".to_string()], true, 0).send()?;
+    assert_eq!((swapped, count), ((), 1));
+    assert_eq!(client.slice([0, 1], false)?, vec!["a"]);
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::thread;
+
+use converse_derive::Converse;
+use converse::error::Error;
+use converse::transport::loopback;
+use converse::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/* an error of the trait's own, which our errors end up in on the client */
+#[derive(Debug)]
+struct Failed(String);
+
+impl Serialize for Failed {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Failed {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d).map(Failed)
+    }
+}
+
+impl From<Error> for Failed {
+    fn from(e: Error) -> Self {
+        Failed(e.to_string())
+    }
+}
+
+#[Converse(module = "remote")]
+trait Playlist {
+    fn add(&mut self, x: &str);
+
+    fn list(&self) -> Vec<String>;
+
+    fn remove(&mut self, i: usize) -> Result<String, Failed>;
+
+    /* only ever called locally */
+    #[converse(skip)]
+    fn len(&self) -> usize {
+        self.list().len()
+    }
+}
+
+struct Local {
+    list: Vec<String>,
+}
+
+impl Playlist for Local {
+    fn add(&mut self, x: &str) {
+        self.list.push(x.to_string());
+    }
+
+    fn list(&self) -> Vec<String> {
+        self.list.clone()
+    }
+
+    fn remove(&mut self, i: usize) -> Result<String, Failed> {
+        match i < self.list.len() {
+            true => Ok(self.list.remove(i)),
+            false => Err(Failed(format!("no item {}", i))),
+        }
+    }
+}
+
+/* served as any implementation, and swapped for one behind dyn Playlist */
+fn _serve(local: Local) -> Result<remote::PlaylistServer<Local>, Error> {
+    remote::PlaylistServer::server(local)
+}
+
+fn _open(remote: bool) -> Result<Box<dyn Playlist>, Error> {
+    match remote {
+        true => Ok(Box::new(remote::PlaylistClient::client()?)),
+        false => Ok(Box::new(Local { list: vec![] })),
+    }
+}
+
+struct Counter {
+    count: u64,
+}
+
+trait Count {
+    fn increment(&mut self) -> u64;
+}
+
+#[Converse(counter)]
+impl Count for Counter {
+    fn increment(&mut self) -> u64 {
+        self.count += 1;
+        self.count
+    }
+}
+
+fn _count(client: &mut CounterClient) -> u64 {
+    Count::increment(client)
+}
+
+fn main() -> Result<(), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = remote::PlaylistServer::server_with(Local { list: vec![] }, listener)?;
+    thread::spawn(move || server.run());
+
+    /* through the trait, as a caller holding any Playlist would */
+    let mut playlist: Box<dyn Playlist> = Box::new(remote::PlaylistClient::client_with(connector)?);
+
+    playlist.add("a");
+    playlist.add("b");
+    assert_eq!(playlist.list(), vec!["a", "b"]);
+    assert_eq!(playlist.len(), 2);
+
+    assert_eq!(playlist.remove(0).unwrap(), "a");
+    match playlist.remove(5) {
+        Err(Failed(e)) => assert_eq!(e, "no item 5"),
+        x => panic!("expected the server's error, got {:?}", x),
+    }
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Counter { count: 0 }.server_with(listener)?;
+    thread::spawn(move || server.run());
+
+    let mut client = Counter::client_with(connector)?;
+    assert_eq!(_count(&mut client), 1);
+    assert_eq!(client.increment()?, 2);
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use converse_derive::Converse;
+
+/* services of one module get types named after them, so they don't clash */
+pub struct Playlist {
+    list: Vec<String>,
+}
+
+#[Converse(playlist)]
+impl Playlist {
+    pub fn add(&mut self, x: String) {
+        self.list.push(x);
+    }
+}
+
+pub struct Counter {
+    count: u64,
+}
+
+#[Converse(counter)]
+impl Counter {
+    pub fn increment(&mut self) -> u64 {
+        self.count += 1;
+        self.count
+    }
+}
+
+/* and can run side by side in one process */
+fn _serve(playlist: Playlist, counter: Counter) -> Result<(), converse::error::Error> {
+    let mut playlist: PlaylistServer = playlist.server()?;
+    let mut counter: CounterServer = counter.server()?;
+
+    let thread = std::thread::spawn(move || playlist.run());
+    counter.run()?;
+
+    thread.join().unwrap()
+}
+
+fn _call(playlist: &mut PlaylistClient, counter: &mut CounterClient) -> Result<u64, converse::error::Error> {
+    playlist.add(format!("song"))?;
+    counter.increment()
+}
+
+fn main() {}
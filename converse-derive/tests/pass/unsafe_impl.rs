@@ -0,0 +1,44 @@
+use std::thread;
+
+use converse_derive::Converse;
+use converse::error::Error;
+use converse::transport::loopback;
+
+/* implementors promise the count never goes down */
+unsafe trait Monotonic {
+    fn increment(&mut self) -> u64;
+}
+
+struct Counter {
+    count: u64,
+}
+
+/* only the client's impl of Monotonic is unsafe */
+#[Converse(counter)]
+unsafe impl Monotonic for Counter {
+    fn increment(&mut self) -> u64 {
+        self.count += 1;
+        self.count
+    }
+}
+
+fn _serve(counter: Counter) -> Result<(), Error> {
+    counter.server()?.run()
+}
+
+fn _count(client: &mut CounterClient) -> u64 {
+    Monotonic::increment(client)
+}
+
+fn main() -> Result<(), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Counter { count: 0 }.server_with(listener)?;
+    thread::spawn(move || server.run());
+
+    let mut client = Counter::client_with(connector)?;
+    assert_eq!(_count(&mut client), 1);
+    assert_eq!(_count(&mut client), 2);
+
+    Ok(())
+}
@@ -0,0 +1,7 @@
+/* what #[Converse] accepts, and the errors it reports for the rest */
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+    t.pass("tests/pass/*.rs");
+}
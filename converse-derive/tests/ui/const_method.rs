@@ -0,0 +1,14 @@
+use converse_derive::Converse;
+
+struct Buffer {
+    items: Vec<u8>,
+}
+
+#[Converse(buffer)]
+impl Buffer {
+    pub fn take<const N: usize>(&mut self) -> Vec<u8> {
+        self.items.drain(..N).collect()
+    }
+}
+
+fn main() {}
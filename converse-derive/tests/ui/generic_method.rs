@@ -0,0 +1,14 @@
+use converse_derive::Converse;
+
+struct Playlist {
+    list: Vec<String>,
+}
+
+#[Converse(playlist)]
+impl Playlist {
+    pub fn add<V: ToString>(&mut self, x: V) {
+        self.list.push(x.to_string());
+    }
+}
+
+fn main() {}
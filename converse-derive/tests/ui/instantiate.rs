@@ -0,0 +1,24 @@
+use converse_derive::Converse;
+
+struct Playlist {
+    list: Vec<String>,
+}
+
+#[Converse(playlist)]
+impl Playlist {
+    #[converse(instantiate(U = String, V = u64, V = u64))]
+    pub fn add<V: ToString>(&mut self, x: V) {
+        self.list.push(x.to_string());
+    }
+
+    #[converse(instantiate(V = String))]
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    pub fn extend(&mut self, x: impl IntoIterator<Item = String>) {
+        self.list.extend(x);
+    }
+}
+
+fn main() {}
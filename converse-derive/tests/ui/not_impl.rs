@@ -0,0 +1,8 @@
+use converse_derive::Converse;
+
+#[Converse(playlist)]
+struct Playlist {
+    list: Vec<String>,
+}
+
+fn main() {}
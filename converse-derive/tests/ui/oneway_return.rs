@@ -0,0 +1,16 @@
+use converse_derive::Converse;
+
+struct Playlist {
+    list: Vec<String>,
+}
+
+#[Converse(playlist)]
+impl Playlist {
+    #[converse(oneway)]
+    pub fn add(&mut self, x: String) -> usize {
+        self.list.push(x);
+        self.list.len()
+    }
+}
+
+fn main() {}
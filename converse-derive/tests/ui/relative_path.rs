@@ -0,0 +1,14 @@
+use converse_derive::Converse;
+
+struct Playlist {
+    list: Vec<String>,
+}
+
+#[Converse(path = "run/playlist")]
+impl Playlist {
+    pub fn list(&self) -> Vec<String> {
+        self.list.clone()
+    }
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+use converse_derive::Converse;
+
+struct Counter {
+    count: u64,
+}
+
+/* the two names hash to the same key */
+#[Converse(counter)]
+impl Counter {
+    pub fn call_946719(&mut self) -> u64 {
+        self.count += 1;
+        self.count
+    }
+
+    pub fn call_1199484(&mut self) -> u64 {
+        self.count -= 1;
+        self.count
+    }
+}
+
+fn main() {}
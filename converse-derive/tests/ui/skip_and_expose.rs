@@ -0,0 +1,15 @@
+use converse_derive::Converse;
+
+struct Playlist {
+    list: Vec<String>,
+}
+
+#[Converse(playlist)]
+impl Playlist {
+    #[converse(skip, expose)]
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+use converse_derive::Converse;
+
+#[Converse(playlist)]
+trait Playlist {
+    type Item;
+
+    fn new() -> Self;
+
+    #[converse(skip)]
+    fn clear(&mut self);
+
+    #[converse(instantiate(V = String))]
+    fn add<V: ToString>(&mut self, x: V);
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use converse_derive::Converse;
+
+#[Converse(name = "playlist", parts(edit))]
+trait Playlist {
+    fn list(&self) -> Vec<String>;
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use converse_derive::Converse;
+
+struct Playlist {
+    list: Vec<String>,
+}
+
+#[Converse(playlist)]
+impl Playlist {
+    #[converse(oneway, fast)]
+    pub fn add(&mut self, x: String) {
+        self.list.push(x);
+    }
+}
+
+fn main() {}
@@ -0,0 +1,84 @@
+use std::env;
+use std::ffi::OsStr;
+use std::os::unix::net::UnixStream;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::procdir::ProcessDirectory;
+
+/*
+ * Launches the server on demand when a client finds nobody listening on the
+ * process directory socket, similar to dbus activation.
+ */
+pub struct AutoStart {
+    command: Command,
+    timeout: Duration,
+}
+
+impl AutoStart {
+    pub fn new(command: Command) -> Self {
+        AutoStart {
+            command: command,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /* re-execute the running binary, `args` should put it in server mode */
+    pub fn current_exe<I, S>(args: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new(env::current_exe()?);
+        command.args(args);
+
+        Ok(AutoStart::new(command))
+    }
+
+    /* how long to wait for the socket to accept connections */
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /* make sure a server is accepting connections on `proc`, starting one if needed */
+    pub fn start(&mut self, proc: &ProcessDirectory) -> Result<(), Error> {
+
+        /* another client may be starting the server right now, wait our turn */
+        let _lock = proc.exclusive()?;
+
+        if UnixStream::connect(proc.socket()).is_ok() {
+            return Ok(());
+        }
+
+        let mut child = self.command
+            .stdin(Stdio::null())
+            .spawn()?;
+
+        let start = Instant::now();
+
+        while UnixStream::connect(proc.socket()).is_err() {
+
+            if let Some(status) = child.try_wait()? {
+                return Err(Error::Client(
+                    format!("Server exited during startup: {}", status)));
+            }
+
+            if start.elapsed() > self.timeout {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(Error::Client(
+                    format!("Timed out waiting for server socket '{}'", proc.socket().display())));
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        /* reap the server whenever it exits so it doesn't linger as a zombie */
+        thread::spawn(move || child.wait());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,645 @@
+use std::collections::HashMap;
+use std::io::{self, prelude::*};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+use crate::protocol::{self, IPCRequest, IPCResponse};
+use crate::transport::{Connector, ReadHalf, Stream, WriteHalf};
+
+use serde::de::DeserializeOwned;
+
+/* limits applied by generated clients, None waits forever */
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub read: Option<Duration>,
+    pub write: Option<Duration>,
+    /*
+     * Budget for a whole call. The resulting deadline is sent along with the
+     * request so the server can skip work nobody is waiting for anymore.
+     */
+    pub call: Option<Duration>,
+}
+
+/*
+ * Retry policy for methods marked #[converse(idempotent)]. Only connection
+ * failures are retried, errors reported by the server are returned as is.
+ * The delay doubles after every attempt up to `max_backoff`.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    /* total number of tries, 1 disables retrying */
+    pub attempts: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            attempts: 1,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/* the part of a generated client that moves requests over a transport */
+pub struct Channel {
+    transport: Box<dyn Connector>,
+    timeouts: Timeouts,
+    retry: Retry,
+    /* set by multiplex(), None means a connection per call */
+    mux: Option<Mutex<Option<Arc<Multiplexer>>>>,
+    /* id of the next request, see exchange */
+    next: AtomicU32,
+}
+
+impl Channel {
+    pub fn new(transport: Box<dyn Connector>) -> Self {
+        Channel {
+            transport: transport,
+            timeouts: Timeouts::default(),
+            retry: Retry::default(),
+            mux: None,
+            next: AtomicU32::new(1),
+        }
+    }
+
+    pub fn timeouts(&mut self) -> &mut Timeouts {
+        &mut self.timeouts
+    }
+
+    pub fn retry(&mut self) -> &mut Retry {
+        &mut self.retry
+    }
+
+    /*
+     * Send every call over one shared connection from now on. Responses are
+     * matched to calls by request id, so calls made concurrently don't wait
+     * for each other unless the server runs them one at a time. The
+     * connection is reopened on the next call if it fails. Servers using
+     * run() serve no other client while it is open.
+     */
+    pub fn multiplex(&mut self) -> Result<(), Error> {
+        let mux = self.open(None)?;
+        self.mux = Some(Mutex::new(Some(mux)));
+        Ok(())
+    }
+
+    /* make a call and return the serialized result */
+    pub fn call(&self, key: u32, argv: Vec<Vec<u8>>) -> Result<Vec<u8>, Error> {
+        let deadline = self.timeouts.call.map(|x| Instant::now() + x);
+        self.attempt(key, argv, deadline)
+    }
+
+    /* like call, but reconnect and replay according to the retry policy */
+    pub fn call_idempotent(&self, key: u32, argv: Vec<Vec<u8>>) -> Result<Vec<u8>, Error> {
+
+        let deadline = self.timeouts.call.map(|x| Instant::now() + x);
+        let mut backoff = self.retry.backoff;
+        let mut tries = 1;
+
+        loop {
+            match self.attempt(key, argv.clone(), deadline) {
+                Err(Error::IOError(_)) if tries < self.retry.attempts => {},
+                x => return x,
+            }
+
+            /* don't sleep past the deadline, the next attempt would fail anyway */
+            let delay = remaining(Some(backoff), deadline)?.unwrap_or(backoff);
+            thread::sleep(delay);
+
+            backoff = (backoff * 2).min(self.retry.max_backoff);
+            tries += 1;
+        }
+    }
+
+    fn attempt(&self, key: u32, argv: Vec<Vec<u8>>, deadline: Option<Instant>) -> Result<Vec<u8>, Error> {
+
+        let mut request = IPCRequest::new(key, argv);
+        request.deadline = deadline.map(epoch_millis).unwrap_or(0);
+
+        let response = (|| {
+            if self.mux.is_some() {
+                let wait = remaining(self.timeouts.read, deadline)?;
+                return self.shared(deadline)?.call(request, wait);
+            }
+
+            let mut stream = self.connect(deadline)?;
+
+            request.id = self.next.fetch_add(1, Ordering::Relaxed);
+            exchange(&mut stream, request)
+        })();
+
+        response.map_err(|e| timed_out(e, &self.timeouts, deadline))?.into_result()
+    }
+
+    /* run `requests` in one round trip, returns the serialized results in order */
+    pub fn batch(&self, requests: Vec<IPCRequest>) -> Result<Vec<Vec<u8>>, Error> {
+
+        let count = requests.len();
+        let data = self.call(protocol::BATCH, IPCRequest::pack(requests)?)?;
+
+        let results = IPCResponse::unpack(&data)?.into_iter()
+            .map(IPCResponse::into_result)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if results.len() != count {
+            return Err(Error::Client(format!("Batch of {} calls got {} results", count, results.len())));
+        }
+
+        Ok(results)
+    }
+
+    /*
+     * Open a connection holding the server's state exclusively, the calls
+     * made through it see no other client's changes until commit or abort.
+     */
+    pub fn begin(&self) -> Result<Transaction, Error> {
+
+        let deadline = self.timeouts.call.map(|x| Instant::now() + x);
+
+        let mut request = IPCRequest::new(protocol::BEGIN, vec![]);
+        request.id = self.next.fetch_add(1, Ordering::Relaxed);
+
+        let opened = (|| {
+            let mut stream = self.connect(deadline)?;
+            let response = exchange(&mut stream, request)?;
+            Ok((stream, response))
+        })();
+
+        let (stream, response) = opened.map_err(|e| timed_out(e, &self.timeouts, deadline))?;
+        response.into_result()?;
+
+        Ok(Transaction {
+            stream: Mutex::new(Some(stream)),
+            timeouts: self.timeouts,
+            next: AtomicU32::new(1),
+        })
+    }
+
+    /* make a #[converse(oneway)] call, returns as soon as the request is written */
+    pub fn send(&self, key: u32, argv: Vec<Vec<u8>>) -> Result<(), Error> {
+
+        let deadline = self.timeouts.call.map(|x| Instant::now() + x);
+
+        let mut request = IPCRequest::new(key, argv);
+        request.deadline = deadline.map(epoch_millis).unwrap_or(0);
+
+        self.post(request, deadline)
+    }
+
+    /* ask the server to shut down, no response is sent. Only honoured over local transports */
+    pub fn exit(&self) -> Result<(), Error> {
+        self.post(IPCRequest::new(0, vec![]), None)
+    }
+
+    /* write a request nobody waits on a response for */
+    fn post(&self, request: IPCRequest, deadline: Option<Instant>) -> Result<(), Error> {
+
+        let posted = (|| {
+            if self.mux.is_some() {
+                return self.shared(deadline)?.send(request);
+            }
+
+            let mut stream = self.connect(deadline)?;
+
+            request.write(&mut stream)?;
+            stream.flush()?;
+
+            Ok(())
+        })();
+
+        posted.map_err(|e| timed_out(e, &self.timeouts, deadline))
+    }
+
+    /* the multiplexed connection, reopened if the last one failed */
+    fn shared(&self, deadline: Option<Instant>) -> Result<Arc<Multiplexer>, Error> {
+
+        let mux = self.mux.as_ref().unwrap();
+        let mut mux = mux.lock().map_err(|_| Error::Client(format!("Multiplexed connection poisoned")))?;
+
+        match *mux {
+            Some(ref x) if !x.closed() => return Ok(x.clone()),
+            _ => {},
+        }
+
+        /* let go of the old connection first, some transports only allow one */
+        mux.take();
+
+        let next = self.open(deadline)?;
+        *mux = Some(next.clone());
+
+        Ok(next)
+    }
+
+    fn open(&self, deadline: Option<Instant>) -> Result<Arc<Multiplexer>, Error> {
+
+        let mut stream = self.connect(deadline)?;
+
+        /* the reader idles between calls, waiting for responses is timed per call instead */
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(self.timeouts.write)?;
+
+        let (reader, writer) = match stream.split() {
+            Ok(x) => x,
+            Err(_) => return Err(Error::Client(format!("Transport can't be multiplexed"))),
+        };
+
+        Ok(Multiplexer::start(reader, writer))
+    }
+
+    fn connect(&self, deadline: Option<Instant>) -> Result<Box<dyn Stream>, Error> {
+
+        let connect = remaining(self.timeouts.connect, deadline)?;
+        let mut stream = match connect {
+            Some(x) => self.transport.connect_timeout(x)?,
+            None => self.transport.connect()?,
+        };
+
+        stream.set_read_timeout(remaining(self.timeouts.read, deadline)?)?;
+        stream.set_write_timeout(remaining(self.timeouts.write, deadline)?)?;
+
+        Ok(stream)
+    }
+}
+
+/*
+ * Client side of a transaction, see Channel::begin. Calls go over the one
+ * connection holding the lock. A call failing on the connection itself
+ * closes it, which makes the server abort, as does dropping the transaction
+ * without commit.
+ */
+pub struct Transaction {
+    stream: Mutex<Option<Box<dyn Stream>>>,
+    timeouts: Timeouts,
+    next: AtomicU32,
+}
+
+impl Transaction {
+    pub fn call(&self, key: u32, argv: Vec<Vec<u8>>) -> Result<Vec<u8>, Error> {
+        self.exchange(IPCRequest::new(key, argv), true)?.into_result()
+    }
+
+    /* make a #[converse(oneway)] call */
+    pub fn send(&self, key: u32, argv: Vec<Vec<u8>>) -> Result<(), Error> {
+        self.exchange(IPCRequest::new(key, argv), false)?;
+        Ok(())
+    }
+
+    /* keep the changes made in the transaction and release the state */
+    pub fn commit(self) -> Result<(), Error> {
+        self.exchange(IPCRequest::new(protocol::COMMIT, vec![]), true)?.into_result()?;
+        Ok(())
+    }
+
+    /* roll back the changes, or only release the state if the server doesn't take snapshots */
+    pub fn abort(self) -> Result<(), Error> {
+        self.exchange(IPCRequest::new(protocol::ABORT, vec![]), true)?.into_result()?;
+        Ok(())
+    }
+
+    /* write `request` and read its response if `reply` */
+    fn exchange(&self, mut request: IPCRequest, reply: bool) -> Result<IPCResponse, Error> {
+
+        let deadline = self.timeouts.call.map(|x| Instant::now() + x);
+        request.deadline = deadline.map(epoch_millis).unwrap_or(0);
+        request.id = self.next.fetch_add(1, Ordering::Relaxed);
+
+        let mut guard = self.stream.lock()
+            .map_err(|_| Error::Client(format!("Transaction connection poisoned")))?;
+
+        let stream = match guard.as_mut() {
+            Some(x) => x,
+            None => return Err(Error::Client(format!("Transaction connection closed after an earlier error"))),
+        };
+
+        let result = (|| {
+            stream.set_read_timeout(remaining(self.timeouts.read, deadline)?)?;
+            stream.set_write_timeout(remaining(self.timeouts.write, deadline)?)?;
+
+            match reply {
+                true => exchange(stream, request),
+                false => {
+                    request.write(stream)?;
+                    stream.flush()?;
+                    Ok(IPCResponse::ok(vec![]))
+                },
+            }
+        })().map_err(|e| timed_out(e, &self.timeouts, deadline));
+
+        /* a response might still be on its way, nothing after this would line up */
+        if result.is_err() {
+            guard.take();
+        }
+
+        result
+    }
+}
+
+/*
+ * Calls queued by a generated batch builder. `T` is the tuple of the results
+ * queued so far, each push appends the result type of one more call.
+ */
+pub struct Batch<'c, T> {
+    requests: Vec<IPCRequest>,
+    /* the first argument that failed to serialize, reported by send */
+    error: Option<Error>,
+    decode: Box<dyn FnOnce(&mut dyn Iterator<Item = Vec<u8>>) -> Result<T, Error> + 'c>,
+}
+
+impl<'c> Batch<'c, ()> {
+    pub fn new() -> Self {
+        Batch {
+            requests: vec![],
+            error: None,
+            decode: Box::new(|_| Ok(())),
+        }
+    }
+}
+
+impl<'c, T: 'c> Batch<'c, T> {
+    /* queue a call to `key` returning an R */
+    pub fn push<R>(self, key: u32, argv: Result<Vec<Vec<u8>>, Error>) -> Batch<'c, T::Output>
+    where
+        T: Append<R>,
+        R: DeserializeOwned + 'c
+    {
+        let mut requests = self.requests;
+        let mut error = self.error;
+
+        match argv {
+            Ok(argv) => requests.push(IPCRequest::new(key, argv)),
+            Err(e) => { error.get_or_insert(e); },
+        }
+
+        let decode = self.decode;
+
+        Batch {
+            requests: requests,
+            error: error,
+            decode: Box::new(move |results| {
+                let prev = decode(results)?;
+                let next = results.next()
+                    .ok_or_else(|| Error::Client(format!("Missing batch result")))?;
+
+                Ok(prev.append(serde_cbor::from_slice(&next)?))
+            }),
+        }
+    }
+
+    /* run the queued calls, fails with the first error if any call failed */
+    pub fn send(self, channel: &Channel) -> Result<T, Error> {
+
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+
+        let results = channel.batch(self.requests)?;
+        (self.decode)(&mut results.into_iter())
+    }
+}
+
+/* appends a value to a tuple, (A, B) and C make (A, B, C) */
+pub trait Append<X> {
+    type Output;
+
+    fn append(self, x: X) -> Self::Output;
+}
+
+macro_rules! append {
+    ($($ty:ident),*) => {
+        impl<$($ty,)* X> Append<X> for ($($ty,)*) {
+            type Output = ($($ty,)* X,);
+
+            #[allow(non_snake_case)]
+            fn append(self, x: X) -> Self::Output {
+                let ($($ty,)*) = self;
+                ($($ty,)* x,)
+            }
+        }
+    }
+}
+
+append!();
+append!(A);
+append!(A, B);
+append!(A, B, C);
+append!(A, B, C, D);
+append!(A, B, C, D, E);
+append!(A, B, C, D, E, F);
+append!(A, B, C, D, E, F, G);
+append!(A, B, C, D, E, F, G, H);
+append!(A, B, C, D, E, F, G, H, I);
+append!(A, B, C, D, E, F, G, H, I, J);
+append!(A, B, C, D, E, F, G, H, I, J, K);
+
+/*
+ * One connection shared by concurrent calls. A reader thread hands each
+ * response to the call with the matching request id.
+ */
+struct Multiplexer {
+    writer: Mutex<WriteHalf>,
+    pending: Arc<Mutex<Pending>>,
+    next: AtomicU32,
+}
+
+/* calls waiting for a response, closed once the connection failed */
+struct Pending {
+    calls: HashMap<u32, Sender<IPCResponse>>,
+    closed: bool,
+}
+
+impl Multiplexer {
+    fn start(mut reader: ReadHalf, writer: WriteHalf) -> Arc<Self> {
+
+        let pending = Arc::new(Mutex::new(Pending {
+            calls: HashMap::new(),
+            closed: false,
+        }));
+
+        let shared = pending.clone();
+        thread::spawn(move || {
+            while let Ok(response) = IPCResponse::read(&mut reader) {
+                let call = shared.lock().ok().and_then(|mut x| x.calls.remove(&response.id));
+
+                /* the caller may have timed out already */
+                if let Some(call) = call {
+                    call.send(response).ok();
+                }
+            }
+
+            /* dropping the senders wakes everyone still waiting */
+            if let Ok(mut x) = shared.lock() {
+                x.closed = true;
+                x.calls.clear();
+            }
+        });
+
+        Arc::new(Multiplexer {
+            writer: Mutex::new(writer),
+            pending: pending,
+            next: AtomicU32::new(1),
+        })
+    }
+
+    /* 0 means no id was given, so skip it when the counter wraps */
+    fn id(&self) -> u32 {
+        loop {
+            match self.next.fetch_add(1, Ordering::Relaxed) {
+                0 => continue,
+                id => return id,
+            }
+        }
+    }
+
+    fn closed(&self) -> bool {
+        self.pending.lock().map(|x| x.closed).unwrap_or(true)
+    }
+
+    /* send `request` and wait up to `wait` for its response */
+    fn call(&self, mut request: IPCRequest, wait: Option<Duration>) -> Result<IPCResponse, Error> {
+
+        request.id = self.id();
+        let id = request.id;
+
+        let (tx, rx) = mpsc::channel();
+        {
+            let mut pending = self.pending.lock().map_err(|_| hung_up())?;
+            if pending.closed {
+                return Err(hung_up().into());
+            }
+            pending.calls.insert(id, tx);
+        }
+
+        if let Err(e) = self.send(request) {
+            self.forget(id);
+            return Err(e);
+        }
+
+        let response = match wait {
+            Some(x) => rx.recv_timeout(x),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match response {
+            Ok(x) => Ok(x),
+            Err(RecvTimeoutError::Timeout) => {
+                self.forget(id);
+
+                /* let the server stop working on it */
+                let mut cancel = IPCRequest::new(protocol::CANCEL, vec![]);
+                cancel.id = id;
+                self.send(cancel).ok();
+
+                Err(Error::Timeout(format!("No response from server in time")))
+            },
+            Err(RecvTimeoutError::Disconnected) => Err(hung_up().into()),
+        }
+    }
+
+    fn send(&self, mut request: IPCRequest) -> Result<(), Error> {
+
+        if request.id == 0 {
+            request.id = self.id();
+        }
+
+        let mut writer = self.writer.lock().map_err(|_| hung_up())?;
+
+        let written = request.write(&mut *writer).and_then(|_| Ok(writer.flush()?));
+
+        /* a partly written frame leaves the connection unusable */
+        if written.is_err() {
+            self.close();
+        }
+
+        written
+    }
+
+    fn forget(&self, id: u32) {
+        if let Ok(mut x) = self.pending.lock() {
+            x.calls.remove(&id);
+        }
+    }
+
+    fn close(&self) {
+        if let Ok(mut x) = self.pending.lock() {
+            x.closed = true;
+            x.calls.clear();
+        }
+    }
+}
+
+/*
+ * Write `request` and read the response to it. A response to another request
+ * is one left over from a call that gave up waiting, the connection is out of
+ * step and must not be used again.
+ */
+fn exchange<S: Read + Write>(stream: &mut S, request: IPCRequest) -> Result<IPCResponse, Error> {
+
+    let id = request.id;
+
+    request.write(stream)?;
+    stream.flush()?;
+
+    let response = IPCResponse::read(stream)?;
+
+    if response.id != id {
+        return Err(Error::Client(format!("Response to request {} received while waiting for request {}", response.id, id)));
+    }
+
+    Ok(response)
+}
+
+/* reported as an io error so idempotent calls are retried on a new connection */
+fn hung_up() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "multiplexed connection closed")
+}
+
+/*
+ * A read, write or connect giving up because of a timeout we set. Sockets
+ * report that as WouldBlock or TimedOut depending on the platform, without
+ * any timeout set those are left as they are.
+ */
+fn timed_out(e: Error, timeouts: &Timeouts, deadline: Option<Instant>) -> Error {
+
+    let limited = deadline.is_some() || timeouts.connect.is_some()
+        || timeouts.read.is_some() || timeouts.write.is_some();
+
+    match e {
+        Error::IOError(ref x) if limited => match x.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Error::Timeout(x.to_string()),
+            _ => e,
+        },
+        e => e,
+    }
+}
+
+/* the tighter of a fixed timeout and whatever is left until the deadline */
+fn remaining(timeout: Option<Duration>, deadline: Option<Instant>) -> Result<Option<Duration>, Error> {
+
+    let left = match deadline {
+        Some(x) => match x.checked_duration_since(Instant::now()) {
+            Some(x) if x > Duration::from_millis(0) => Some(x),
+            _ => return Err(Error::Timeout(format!("Call deadline passed"))),
+        },
+        None => None,
+    };
+
+    Ok(match (timeout, left) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    })
+}
+
+fn epoch_millis(deadline: Instant) -> u64 {
+    let left = deadline.saturating_duration_since(Instant::now());
+    let at = SystemTime::now() + left;
+
+    at.duration_since(UNIX_EPOCH).map(|x| x.as_millis() as u64).unwrap_or(0)
+}
@@ -0,0 +1,142 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::transport::{HangUp, Peer};
+
+thread_local! {
+    static CURRENT: RefCell<Option<Context>> = RefCell::new(None);
+}
+
+/* per call information made available to the method being dispatched */
+#[derive(Clone, Debug)]
+pub struct Context {
+    peer: Peer,
+    deadline: Option<SystemTime>,
+    cancel: CancelToken,
+}
+
+impl Context {
+    pub fn new(peer: Peer) -> Self {
+        Context {
+            peer: peer,
+            deadline: None,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /* deadline as sent on the wire, milliseconds since the epoch and 0 for none */
+    pub fn with_deadline(mut self, deadline: u64) -> Self {
+        self.deadline = match deadline {
+            0 => None,
+            x => Some(UNIX_EPOCH + Duration::from_millis(x)),
+        };
+        self
+    }
+
+    pub fn peer<'a>(&'a self) -> &'a Peer {
+        &self.peer
+    }
+
+    /* when the caller stops waiting for the result */
+    pub fn deadline(&self) -> Option<SystemTime> {
+        self.deadline
+    }
+
+    /* long running methods can poll this to give up early */
+    pub fn expired(&self) -> bool {
+        self.deadline.map(|x| SystemTime::now() >= x).unwrap_or(false)
+    }
+
+    /* the caller cancelled the call or hung up, nobody will see the result */
+    pub fn cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /* for handing cancellation on to other threads */
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+}
+
+/*
+ * Cancellation of one call. Triggered by a cancel message from the client
+ * or, where the transport can tell, by the client hanging up.
+ */
+#[derive(Clone)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    hang_up: Option<HangUp>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            hang_up: None,
+        }
+    }
+
+    /* also count as cancelled once `hang_up` reports the client gone */
+    pub fn watch(mut self, hang_up: HangUp) -> Self {
+        self.hang_up = Some(hang_up);
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return true;
+        }
+
+        match self.hang_up {
+            Some(ref x) if x() => {
+                self.cancel();
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.cancelled.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+/* context of the call currently running on this thread, if any */
+pub fn current() -> Option<Context> {
+    CURRENT.with(|x| x.borrow().clone())
+}
+
+/* run `f` with `context` installed as the current context */
+pub fn scope<F, R>(context: Context, f: F) -> R
+where
+    F: FnOnce() -> R
+{
+    let _guard = Guard(CURRENT.with(|x| x.replace(Some(context))));
+    f()
+}
+
+/* restores the outer context even if `f` unwinds */
+struct Guard(Option<Context>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let prev = self.0.take();
+        CURRENT.with(|x| *x.borrow_mut() = prev);
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, prelude::*};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+use std::process;
+
+use crate::error::Error;
+
+/*
+ * Detach from the terminal: double fork, start a new session and send stdio
+ * to `log`. Only returns in the daemon. The launching process blocks until
+ * the daemon calls `Daemon::ready` or `Daemon::failed` and then exits with
+ * status 0 or 1 respectively, so it can be used as a forking service.
+ */
+pub fn daemonize<P: AsRef<Path>>(log: P) -> Result<Daemon, Error> {
+
+    /* open these before forking so errors still reach the caller */
+    let log = OpenOptions::new().create(true).append(true).open(log)?;
+    let null = File::open("/dev/null")?;
+
+    let mut fds = [0 as RawFd; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+    match fork()? {
+        0 => {},
+        pid => {
+            drop(write);
+            launcher(read, pid);
+        },
+    }
+
+    drop(read);
+
+    /* a session leader, then fork again so we can never reacquire a terminal */
+    unsafe {
+        if libc::setsid() < 0 {
+            libc::_exit(1);
+        }
+
+        match libc::fork() {
+            0 => {},
+            -1 => libc::_exit(1),
+            _ => libc::_exit(0),
+        }
+
+        libc::umask(0o022);
+    }
+
+    std::env::set_current_dir("/")?;
+
+    for &(src, dst) in [(null.as_raw_fd(), 0), (log.as_raw_fd(), 1), (log.as_raw_fd(), 2)].iter() {
+        if unsafe { libc::dup2(src, dst) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+
+    Ok(Daemon {
+        status: write,
+    })
+}
+
+/* handle used by the daemon to report back to the launching process */
+pub struct Daemon {
+    status: File,
+}
+
+impl Daemon {
+    pub fn ready(mut self) {
+        self.status.write_all(b"ok").ok();
+    }
+
+    pub fn failed(mut self, error: &Error) {
+        write!(self.status, "{}", error).ok();
+    }
+}
+
+fn fork() -> Result<libc::pid_t, Error> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error().into()),
+        pid => Ok(pid),
+    }
+}
+
+/* original process: wait for the daemon to report and exit accordingly */
+fn launcher(mut status: File, child: libc::pid_t) -> ! {
+
+    let mut message = String::new();
+    status.read_to_string(&mut message).ok();
+
+    unsafe { libc::waitpid(child, std::ptr::null_mut(), 0) };
+
+    match message.as_str() {
+        "ok" => process::exit(0),
+        "" => eprintln!("daemon exited before becoming ready"),
+        _ => eprintln!("daemon failed to start: {}", message),
+    }
+
+    process::exit(1);
+}
@@ -14,6 +14,9 @@ pub enum Error {
     IOError(io::Error),
     FromUtf8Error(FromUtf8Error),
     Serialize(cbor::Error),
+    Tls(String),
+    Handshake(String),
+    Timeout(String),
 }
 
 impl fmt::Display for Error {
@@ -25,6 +28,9 @@ impl fmt::Display for Error {
             Error::IOError(e) => write!(f, "{}", e),
             Error::FromUtf8Error(e) => write!(f, "{}", e),
             Error::Serialize(e) => write!(f, "{}", e),
+            Error::Tls(s) => write!(f, "TLS error: {}", s),
+            Error::Handshake(s) => write!(f, "Handshake error: {}", s),
+            Error::Timeout(s) => write!(f, "Timed out: {}", s),
         }
     }
 }
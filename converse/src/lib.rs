@@ -1,7 +1,17 @@
 pub mod protocol;
 pub mod error;
 pub mod procdir;
+pub mod autostart;
+pub mod daemon;
+pub mod client;
+pub mod transport;
+pub mod context;
+pub mod transaction;
+pub mod server;
+pub mod registry;
 
 pub extern crate serde;
 pub extern crate serde_cbor;
 pub extern crate ctrlc;
+#[cfg(feature = "tls")]
+pub extern crate rustls;
@@ -1,12 +1,17 @@
 use std::fs::{self,File};
-use std::io::prelude::*;
+use std::io::{self, prelude::*};
+use std::os::unix::io::AsRawFd;
 
 use std::env;
 use std::process;
 use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
 
 use crate::error::Error;
 
+/* directories of the servers in this process, None until the handler is set */
+static INTERRUPT: Mutex<Option<Vec<ProcessDirectory>>> = Mutex::new(None);
+
 pub struct ProcessDirectory {
     path:   PathBuf,
     lock:   PathBuf,
@@ -71,15 +76,22 @@ impl ProcessDirectory {
 
     pub fn lock(&self) -> Result<(), Error> {
 
-        /* can't lock if the a different pid is in the lockfile */
-        let pid = self.read_pid();
-        if pid.is_ok() && pid.unwrap() != process::id() {
-            return Err(Error::ProcessDirectory(
-                format!("Failed to lock process directory: '{}'", self.path.display())
-            ))
+        /* can't lock if a different, still running pid is in the lockfile */
+        if let Ok(pid) = self.read_pid() {
+            if pid != process::id() && alive(pid) {
+                return Err(Error::ProcessDirectory(
+                    format!("Failed to lock process directory: '{}'", self.path.display())
+                ))
+            }
         }
 
-        /* either getting pid from file failed or it is corrupted */
+        /* the lockfile is missing, corrupted or left by a dead process */
+        self.relock()
+    }
+
+    /* take over the lock unconditionally, e.g. from the process that forked us */
+    pub fn relock(&self) -> Result<(), Error> {
+
         if self.lock.exists() {
             fs::remove_file(&self.lock)?;
         }
@@ -92,6 +104,23 @@ impl ProcessDirectory {
         Ok(())
     }
 
+    /*
+     * Exclusive advisory lock on the directory itself, held until the
+     * returned guard is dropped. Used to serialize server startup.
+     */
+    pub fn exclusive(&self) -> Result<DirectoryLock, Error> {
+
+        let file = File::open(&self.path)?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(DirectoryLock {
+            file: file,
+        })
+    }
+
     pub fn path<'a>(&'a self) -> &'a PathBuf {
         &self.path
     }
@@ -104,7 +133,34 @@ impl ProcessDirectory {
         &self.socket
     }
 
-    pub fn close(&mut self) {
+    /*
+     * Close the directory and exit when the process is interrupted. There
+     * is one handler per process, set by the first server to run, which
+     * closes the directories of every server in the process.
+     */
+    pub fn remove_on_interrupt(&self) -> Result<(), Error> {
+
+        let mut dirs = INTERRUPT.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if dirs.is_none() {
+            ctrlc::set_handler(interrupted).map_err(|e| Error::ProcessDirectory(
+                format!("Failed to set interrupt handler for server: {}", e)))?;
+        }
+
+        let dirs = dirs.get_or_insert_with(Vec::new);
+
+        if !dirs.iter().any(|x| x.path == self.path) {
+            dirs.push(ProcessDirectory {
+                path: self.path.clone(),
+                lock: self.lock.clone(),
+                socket: self.socket.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn close(&self) {
         let pid = self.read_pid().unwrap_or(0) == process::id();
 
         if pid {
@@ -123,3 +179,30 @@ impl ProcessDirectory {
 
     }
 }
+
+pub struct DirectoryLock {
+    file: File,
+}
+
+impl Drop for DirectoryLock {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+fn interrupted() {
+
+    let dirs = INTERRUPT.lock().unwrap_or_else(PoisonError::into_inner);
+
+    for dir in dirs.iter().flatten() {
+        dir.close();
+    }
+
+    process::exit(0);
+}
+
+/* a lockfile left behind by a crashed server should not block a restart */
+fn alive(pid: u32) -> bool {
+    let ret = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    ret == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
@@ -1,11 +1,41 @@
 use std::u32;
-use std::mem;
 use std::io::{self, prelude::*};
 
 use crate::error::Error;
 
+/*
+ * Key of a request whose arguments are whole packed requests. The server runs
+ * them in order while holding the state exclusively and stops at the first
+ * error. The response data is the packed responses of the calls that ran.
+ */
+pub const BATCH: u32 = u32::MAX;
+
+/*
+ * Transaction control. After BEGIN the connection holds the server's state
+ * exclusively until COMMIT or ABORT, or until the client hangs up which
+ * counts as ABORT.
+ */
+pub const BEGIN: u32 = u32::MAX - 1;
+pub const COMMIT: u32 = u32::MAX - 2;
+pub const ABORT: u32 = u32::MAX - 3;
+
+/* the client lost interest in the call with this request's id, not answered */
+pub const CANCEL: u32 = u32::MAX - 4;
+
+/*
+ * Limits on what a peer can make us allocate before it has sent the data.
+ * Frames are read from unauthenticated connections, anything larger is
+ * refused and the connection dropped. Integers are sent big-endian.
+ */
+pub const MAX_ARGS: u32 = 4096;
+pub const MAX_BUFFER: u32 = 64 * 1024 * 1024;
+
 pub struct IPCRequest {
+    /* chosen by the client and echoed in the response, so calls can be answered out of order */
+    pub id: u32,
     pub key: u32,
+    /* milliseconds since the unix epoch after which the caller gives up, 0 for never */
+    pub deadline: u64,
     pub argc: u32,
     pub argv: Vec<IPCBuffer>,
 }
@@ -15,13 +45,32 @@ pub struct IPCBuffer {
     pub data: Vec<u8>,
 }
 
+pub struct IPCResponse {
+    /* id of the request this answers */
+    pub id: u32,
+    pub status: Status,
+    pub data: IPCBuffer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /* data is the serialized return value */
+    Ok,
+    /* data is a utf8 error message */
+    Error,
+    /* the deadline passed before the call was dispatched */
+    Timeout,
+}
+
 impl IPCRequest {
     pub fn new(key: u32, argv: Vec<Vec<u8>>) -> Self {
 
         let argv: Vec<_> = argv.into_iter().map(IPCBuffer::new).collect();
 
         IPCRequest {
+            id: 0,
             key: key,
+            deadline: 0,
             argc: argv.len() as u32,
             argv: argv,
         }
@@ -29,9 +78,16 @@ impl IPCRequest {
 
     pub fn read<T: Read>(stream: &mut T) -> Result<Self, Error> {
 
+        let id = stream.read_u32()?;
         let key = stream.read_u32()?;
+        let deadline = stream.read_u64()?;
         let argc = stream.read_u32()?;
-        let mut argv = Vec::with_capacity(argc as usize);
+
+        if argc > MAX_ARGS {
+            return Err(too_large(format!("Request with {} arguments, at most {} are allowed", argc, MAX_ARGS)));
+        }
+
+        let mut argv = Vec::with_capacity(argc.min(64) as usize);
 
         for _ in 0..argc {
             let buffer = IPCBuffer::read(stream)?;
@@ -39,7 +95,9 @@ impl IPCRequest {
         }
 
         Ok(IPCRequest {
+            id,
             key,
+            deadline,
             argc,
             argv,
         })
@@ -47,7 +105,9 @@ impl IPCRequest {
 
     pub fn write<T: Write>(self, stream: &mut T) -> Result<(), Error> {
 
+        stream.write_u32(self.id)?;
         stream.write_u32(self.key)?;
+        stream.write_u64(self.deadline)?;
         stream.write_u32(self.argc)?;
 
         assert!(self.argc == self.argv.len() as u32);
@@ -58,6 +118,20 @@ impl IPCRequest {
 
         Ok(())
     }
+
+    /* serialize requests to be sent as the arguments of a BATCH request */
+    pub fn pack(requests: Vec<IPCRequest>) -> Result<Vec<Vec<u8>>, Error> {
+        requests.into_iter().map(|x| {
+            let mut buf = vec![];
+            x.write(&mut buf)?;
+            Ok(buf)
+        }).collect()
+    }
+
+    /* the requests inside a BATCH request */
+    pub fn unpack(&self) -> Result<Vec<IPCRequest>, Error> {
+        self.argv.iter().map(|x| IPCRequest::read(&mut &x.data[..])).collect()
+    }
 }
 
 impl IPCBuffer {
@@ -75,9 +149,18 @@ impl IPCBuffer {
 
     pub fn read<T: Read>(stream: &mut T) -> Result<Self, Error> {
         let len = stream.read_u32()?;
-        let mut buffer = vec![0; len as usize];
 
-        stream.read_exact(&mut buffer[..])?;
+        if len > MAX_BUFFER {
+            return Err(too_large(format!("Buffer of {} bytes, at most {} are allowed", len, MAX_BUFFER)));
+        }
+
+        /* grows as the data arrives rather than trusting len up front */
+        let mut buffer = Vec::with_capacity((len as usize).min(64 * 1024));
+        stream.take(len as u64).read_to_end(&mut buffer)?;
+
+        if buffer.len() != len as usize {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
 
         Ok(IPCBuffer {
             len: len,
@@ -95,6 +178,11 @@ impl IPCBuffer {
                 )))
         }
 
+        /* the peer would refuse it */
+        if self.len > MAX_BUFFER {
+            return Err(too_large(format!("Buffer of {} bytes, at most {} can be sent", self.len, MAX_BUFFER)));
+        }
+
         stream.write_u32(self.len)?;
         stream.write_all(&self.data[..])?;
 
@@ -102,31 +190,139 @@ impl IPCBuffer {
     }
 }
 
+impl IPCResponse {
+    pub fn ok(data: Vec<u8>) -> Self {
+
+        /* tell the caller, rather than failing to write the response */
+        if data.len() > MAX_BUFFER as usize {
+            return IPCResponse::error(&Error::Server(
+                format!("Result of {} bytes is too large to send, at most {} are allowed", data.len(), MAX_BUFFER)));
+        }
+
+        IPCResponse {
+            id: 0,
+            status: Status::Ok,
+            data: IPCBuffer::new(data),
+        }
+    }
+
+    pub fn error(e: &Error) -> Self {
+
+        /* the client reports it as a server error, don't say so twice */
+        let message = match e {
+            Error::Server(s) => s.clone(),
+            e => e.to_string(),
+        };
+
+        IPCResponse {
+            id: 0,
+            status: Status::Error,
+            data: IPCBuffer::new(message.into_bytes()),
+        }
+    }
+
+    pub fn timeout() -> Self {
+        IPCResponse {
+            id: 0,
+            status: Status::Timeout,
+            data: IPCBuffer::new(vec![]),
+        }
+    }
+
+    pub fn read<T: Read>(stream: &mut T) -> Result<Self, Error> {
+
+        let id = stream.read_u32()?;
+        let status = match stream.read_u32()? {
+            0 => Status::Ok,
+            1 => Status::Error,
+            2 => Status::Timeout,
+            x => return Err(Error::Client(format!("Invalid response status: {}", x))),
+        };
+
+        Ok(IPCResponse {
+            id: id,
+            status: status,
+            data: IPCBuffer::read(stream)?,
+        })
+    }
+
+    pub fn write<T: Write>(self, stream: &mut T) -> Result<(), Error> {
+
+        let status = match self.status {
+            Status::Ok => 0,
+            Status::Error => 1,
+            Status::Timeout => 2,
+        };
+
+        stream.write_u32(self.id)?;
+        stream.write_u32(status)?;
+        self.data.write(stream)
+    }
+
+    /* serialize the responses to a BATCH request into one buffer */
+    pub fn pack(responses: Vec<IPCResponse>) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![];
+        for x in responses {
+            x.write(&mut buf)?;
+        }
+        Ok(buf)
+    }
+
+    pub fn unpack(mut data: &[u8]) -> Result<Vec<IPCResponse>, Error> {
+        let mut responses = vec![];
+        while !data.is_empty() {
+            responses.push(IPCResponse::read(&mut data)?);
+        }
+        Ok(responses)
+    }
+
+    /* the serialized return value, or the error the server reported */
+    pub fn into_result(self) -> Result<Vec<u8>, Error> {
+        match self.status {
+            Status::Ok => Ok(self.data.data),
+            Status::Error => Err(Error::Server(String::from_utf8(self.data.data)?)),
+            Status::Timeout => Err(Error::Timeout(format!("Deadline passed before the server dispatched the call"))),
+        }
+    }
+}
+
+/* the frame can't be read or written, the connection is out of step after it */
+fn too_large(message: String) -> Error {
+    Error::IOError(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
 impl<T: Read> ReadU32 for T { }
 impl<T: Write> WriteU32 for T { }
+impl<T: Read> ReadU64 for T { }
+impl<T: Write> WriteU64 for T { }
 trait ReadU32 where Self: Read {
     fn read_u32(&mut self) -> Result<u32, io::Error> {
 
         let mut buf = [0_u8; 4];
         self.read_exact(&mut buf[..])?;
 
-        let value = unsafe {
-            mem::transmute::<[u8;4], u32>(buf)
-        };
-
-        Ok(value)
+        Ok(u32::from_be_bytes(buf))
     }
 }
 
 trait WriteU32 where Self: Write {
     fn write_u32(&mut self, x: u32) -> Result<(), io::Error> {
+        self.write_all(&x.to_be_bytes())
+    }
+}
 
-        let buf = unsafe {
-            mem::transmute::<u32, [u8;4]>(x)
-        };
+trait ReadU64 where Self: Read {
+    fn read_u64(&mut self) -> Result<u64, io::Error> {
+
+        let mut buf = [0_u8; 8];
+        self.read_exact(&mut buf[..])?;
 
-        self.write_all(&buf)?;
+        Ok(u64::from_be_bytes(buf))
+    }
+}
 
-        Ok(())
+trait WriteU64 where Self: Write {
+    fn write_u64(&mut self, x: u64) -> Result<(), io::Error> {
+        self.write_all(&x.to_be_bytes())
     }
 }
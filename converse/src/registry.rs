@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::protocol::IPCRequest;
+use crate::server::Shared;
+
+/* run a request on the shared state, locking it as the method needs */
+pub type Dispatch<S> = fn(&Shared<S>, &IPCRequest) -> Result<Vec<u8>, Error>;
+/* run a request on state locked by the caller, in a batch or a transaction */
+pub type DispatchLocked<S> = fn(&mut S, &IPCRequest) -> Result<Vec<u8>, Error>;
+
+/*
+ * The methods of one #[Converse] impl block. A service is made of the block
+ * with the server and client, and the blocks it names in parts(...).
+ */
+pub struct Part<S> {
+    name: &'static str,
+    /* method, or method<types> of an instantiation, and its key */
+    methods: &'static [(&'static str, u32)],
+    oneway: &'static [u32],
+    dispatch: Dispatch<S>,
+    locked: DispatchLocked<S>,
+}
+
+impl<S> Part<S> {
+    pub fn new(
+        name: &'static str,
+        methods: &'static [(&'static str, u32)],
+        oneway: &'static [u32],
+        dispatch: Dispatch<S>,
+        locked: DispatchLocked<S>,
+    ) -> Self {
+        Part {
+            name: name,
+            methods: methods,
+            oneway: oneway,
+            dispatch: dispatch,
+            locked: locked,
+        }
+    }
+}
+
+/*
+ * Every method of a service by key. Keys are derived from the method names,
+ * so the parts can be registered in any order.
+ */
+pub struct Registry<S> {
+    parts: Vec<Part<S>>,
+    /* index into parts */
+    keys: HashMap<u32, usize>,
+}
+
+impl<S> Registry<S> {
+    pub fn new() -> Self {
+        Registry {
+            parts: vec![],
+            keys: HashMap::new(),
+        }
+    }
+
+    /* add the methods of `part`, none of its keys may be taken already */
+    pub fn register(&mut self, part: Part<S>) -> Result<(), Error> {
+
+        for (i, (method, key)) in part.methods.iter().enumerate() {
+
+            /* #[Converse] reports these already, unless the part was put together by hand */
+            if let Some((other, _)) = part.methods[..i].iter().find(|(_, x)| x == key) {
+                return Err(Error::Server(format!("{} and {} of {} have the same key, rename one of them",
+                    other, method, part.name)));
+            }
+
+            if let Some(&i) = self.keys.get(key) {
+                let other = &self.parts[i];
+                let (taken, _) = other.methods.iter().find(|(_, x)| x == key).unwrap();
+
+                return Err(Error::Server(format!("{} of {} and {} of {} have the same key, rename one of them",
+                    method, part.name, taken, other.name)));
+            }
+        }
+
+        let index = self.parts.len();
+        self.keys.extend(part.methods.iter().map(|&(_, key)| (key, index)));
+        self.parts.push(part);
+
+        Ok(())
+    }
+
+    pub fn is_oneway(&self, key: u32) -> bool {
+        match self.keys.get(&key) {
+            Some(&i) => self.parts[i].oneway.contains(&key),
+            None => false,
+        }
+    }
+
+    pub fn dispatch(&self, shared: &Shared<S>, req: &IPCRequest) -> Result<Vec<u8>, Error> {
+        match self.keys.get(&req.key) {
+            Some(&i) => (self.parts[i].dispatch)(shared, req),
+            None => Err(Error::Server(format!("Invalid function called"))),
+        }
+    }
+
+    pub fn dispatch_locked(&self, state: &mut S, req: &IPCRequest) -> Result<Vec<u8>, Error> {
+        match self.keys.get(&req.key) {
+            Some(&i) => (self.parts[i].locked)(state, req),
+            None => Err(Error::Server(format!("Invalid function called"))),
+        }
+    }
+}
+
+impl<S> Default for Registry<S> {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
@@ -0,0 +1,164 @@
+use std::any::Any;
+use std::process;
+use std::sync::{Condvar, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::procdir::ProcessDirectory;
+use crate::registry::Registry;
+use crate::transaction::{self, Snapshot};
+
+/* what a panic in a method means for the calls after it */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /*
+     * The state may have been left half updated, so every later call fails
+     * until the server is restarted. Open transactions can still be aborted.
+     */
+    Poison,
+    /* carry on with the state as the panicking method left it */
+    Recover,
+    /* clean up the process directory and exit, like a crash but tidy */
+    Exit,
+}
+
+/* calls of one multiplexed connection running at once, unless set otherwise */
+pub const MAX_CALLS: usize = 64;
+
+impl Default for PanicPolicy {
+    fn default() -> Self {
+        PanicPolicy::Poison
+    }
+}
+
+/* the parts of a generated server the threads serving connections share */
+pub struct Shared<S> {
+    pub state: RwLock<S>,
+    /* the methods requests are dispatched to */
+    pub registry: Registry<S>,
+    pub snapshot: Option<Snapshot<S>>,
+    pub panics: PanicPolicy,
+    /* see Slots */
+    pub calls: usize,
+    /* see transaction::TIMEOUT */
+    pub transaction_timeout: Option<Duration>,
+    pub proc: Option<ProcessDirectory>,
+    poisoned: AtomicBool,
+}
+
+impl<S> Shared<S> {
+    pub fn new(state: S, registry: Registry<S>, proc: Option<ProcessDirectory>) -> Self {
+        Shared {
+            state: RwLock::new(state),
+            registry: registry,
+            snapshot: None,
+            panics: PanicPolicy::default(),
+            calls: MAX_CALLS,
+            transaction_timeout: Some(transaction::TIMEOUT),
+            proc: proc,
+            poisoned: AtomicBool::new(false),
+        }
+    }
+
+    /*
+     * Lock the state. The lock's own poisoning is ignored, whether a panic
+     * poisons the state is up to the policy, see panicked.
+     */
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, S>, Error> {
+        self.check()?;
+        Ok(self.state.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, S>, Error> {
+        self.check()?;
+        Ok(self.state.write().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn check(&self) -> Result<(), Error> {
+        match self.poisoned.load(Ordering::SeqCst) {
+            true => Err(Error::Server(format!("Server state poisoned by a panicked call"))),
+            false => Ok(()),
+        }
+    }
+
+    /* apply the policy after a method panicked, returns the error for the caller */
+    pub fn panicked(&self, payload: Box<dyn Any + Send>) -> Error {
+
+        let message = match payload.downcast::<String>() {
+            Ok(x) => *x,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(x) => x.to_string(),
+                Err(_) => format!("unknown cause"),
+            },
+        };
+
+        match self.panics {
+            PanicPolicy::Poison => self.poisoned.store(true, Ordering::SeqCst),
+            PanicPolicy::Recover => {},
+            PanicPolicy::Exit => {
+                self.close();
+                process::exit(1);
+            },
+        }
+
+        Error::Server(format!("Method panicked: {}", message))
+    }
+
+    /* shut down as asked by a client */
+    pub fn exit(&self) -> ! {
+        self.close();
+        process::exit(0);
+    }
+
+    fn close(&self) {
+        if let Some(ref proc) = self.proc {
+            proc.close();
+        }
+    }
+}
+
+/*
+ * Bounds the calls a multiplexed connection runs at once. Taking a slot
+ * waits until one is free, so a client sending faster than the server
+ * works stops being read from instead of costing a thread per request.
+ */
+pub struct Slots {
+    running: Mutex<usize>,
+    freed: Condvar,
+    max: usize,
+}
+
+/* a running call, frees its slot when dropped */
+pub struct Slot<'a> {
+    slots: &'a Slots,
+}
+
+impl Slots {
+    pub fn new(max: usize) -> Self {
+        Slots {
+            running: Mutex::new(0),
+            freed: Condvar::new(),
+            max: max.max(1),
+        }
+    }
+
+    pub fn take(&self) -> Slot<'_> {
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+
+        while *running >= self.max {
+            running = self.freed.wait(running).unwrap_or_else(PoisonError::into_inner);
+        }
+
+        *running += 1;
+        Slot { slots: self }
+    }
+}
+
+impl<'a> Drop for Slot<'a> {
+    fn drop(&mut self) {
+        let mut running = self.slots.running.lock().unwrap_or_else(PoisonError::into_inner);
+        *running -= 1;
+        self.slots.freed.notify_one();
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::RwLockWriteGuard;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::server::Shared;
+
+/*
+ * How long the server waits for the next call of an open transaction before
+ * hanging up on the client and aborting it, unless set otherwise.
+ */
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/* copies the state when a transaction begins, see the generated rollback() */
+pub type Snapshot<S> = fn(&S) -> S;
+
+/*
+ * Server side of a client transaction: exclusive access to the state for the
+ * calls made on one connection. Dropped without commit, e.g. because the
+ * client hung up or went quiet for longer than the transaction timeout, the
+ * state is restored from the snapshot if one was taken.
+ */
+pub struct Transaction<'a, S> {
+    guard: RwLockWriteGuard<'a, S>,
+    snapshot: Option<S>,
+}
+
+impl<'a, S> Transaction<'a, S> {
+    /* blocks until every other call and transaction is done with the state */
+    pub fn begin(shared: &'a Shared<S>) -> Result<Self, Error> {
+
+        let guard = shared.write()?;
+
+        Ok(Transaction {
+            snapshot: shared.snapshot.map(|x| x(&guard)),
+            guard: guard,
+        })
+    }
+
+    pub fn state(&mut self) -> &mut S {
+        &mut self.guard
+    }
+
+    /* keep the changes */
+    pub fn commit(mut self) {
+        self.snapshot = None;
+    }
+
+    /*
+     * Undo the changes if a snapshot was taken. Without one the state is
+     * only released, the transaction kept other clients out but can't undo.
+     */
+    pub fn abort(self) {
+        /* restored on drop */
+    }
+}
+
+impl<'a, S> Drop for Transaction<'a, S> {
+    fn drop(&mut self) {
+        if let Some(x) = self.snapshot.take() {
+            *self.guard = x;
+        }
+    }
+}
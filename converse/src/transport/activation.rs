@@ -0,0 +1,108 @@
+use std::env;
+use std::io;
+use std::mem;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::process;
+
+use crate::error::Error;
+use crate::transport::Listener;
+use crate::transport::tcp::TcpListener;
+use crate::transport::unix::UnixListener;
+
+/* first descriptor handed over by the service manager */
+pub const LISTEN_FDS_START: RawFd = 3;
+
+/*
+ * Sockets passed by a service manager using the systemd LISTEN_FDS protocol.
+ * Empty when the variables are unset or were meant for another process. The
+ * variables are cleared so they are not inherited by our own children.
+ */
+pub fn listen_fds() -> Result<Vec<RawFd>, Error> {
+
+    let pid = env::var("LISTEN_PID");
+    let fds = env::var("LISTEN_FDS");
+
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    let (pid, fds) = match (pid, fds) {
+        (Ok(pid), Ok(fds)) => (pid, fds),
+        _ => return Ok(vec![]),
+    };
+
+    let pid: u32 = pid.trim().parse().map_err(|_| Error::Server(
+        format!("Invalid LISTEN_PID given: '{}'", pid)))?;
+
+    if pid != process::id() {
+        return Ok(vec![]);
+    }
+
+    let invalid = || Error::Server(format!("Invalid LISTEN_FDS given: '{}'", fds));
+
+    let count: RawFd = fds.trim().parse().map_err(|_| invalid())?;
+    let end = match count >= 0 {
+        true => LISTEN_FDS_START.checked_add(count).ok_or_else(invalid)?,
+        false => return Err(invalid()),
+    };
+
+    let fds: Vec<_> = (LISTEN_FDS_START..end).collect();
+
+    for fd in fds.iter() {
+        if unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+
+    Ok(fds)
+}
+
+/* listener for the first socket passed in LISTEN_FDS, if any */
+pub fn listener() -> Result<Option<Box<dyn Listener>>, Error> {
+    match listen_fds()?.first() {
+        Some(fd) => Ok(Some(unsafe { from_raw_fd(*fd)? })),
+        None => Ok(None),
+    }
+}
+
+/// Wrap a listening unix or tcp socket.
+///
+/// # Safety
+///
+/// `fd` must be an open descriptor owned by the caller. Ownership is taken,
+/// so it must not be closed or used elsewhere afterwards.
+pub unsafe fn from_raw_fd(fd: RawFd) -> Result<Box<dyn Listener>, Error> {
+
+    let mut listening: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+
+    let ret = libc::getsockopt(
+        fd,
+        libc::SOL_SOCKET,
+        libc::SO_ACCEPTCONN,
+        &mut listening as *mut libc::c_int as *mut libc::c_void,
+        &mut len,
+    );
+
+    if ret != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    if listening == 0 {
+        return Err(Error::Server(format!("File descriptor {} is not a listening socket", fd)));
+    }
+
+    let mut addr: libc::sockaddr_storage = mem::zeroed();
+    let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+    if libc::getsockname(fd, &mut addr as *mut _ as *mut libc::sockaddr, &mut len) != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    match addr.ss_family as libc::c_int {
+        libc::AF_UNIX => Ok(Box::new(UnixListener::from_raw_fd(fd))),
+        libc::AF_INET | libc::AF_INET6 => Ok(Box::new(TcpListener::from_raw_fd(fd))),
+        family => Err(Error::Server(
+            format!("Unsupported address family {} for file descriptor {}", family, fd))),
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::{self, prelude::*};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::transport::{Connector, Listener, Peer, ReadHalf, Reader, Stream, WriteHalf};
+
+/*
+ * In-memory transport for running a client and server in the same process.
+ * The server side runs until every connector has been dropped:
+ *
+ *     let (listener, connector) = loopback::pair();
+ *     let mut server = state.server_with(listener)?;
+ *     thread::spawn(move || server.run());
+ *     let client = Type::client_with(connector)?;
+ */
+pub fn pair() -> (LoopbackListener, LoopbackConnector) {
+    let (tx, rx) = mpsc::channel();
+
+    (LoopbackListener { incoming: rx }, LoopbackConnector { outgoing: tx })
+}
+
+pub struct LoopbackListener {
+    incoming: Receiver<Pipe>,
+}
+
+impl Listener for LoopbackListener {
+    fn accept(&self) -> Result<Option<(Box<dyn Stream>, Peer)>, Error> {
+        match self.incoming.recv() {
+            Ok(pipe) => Ok(Some((Box::new(pipe), Peer::Loopback))),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoopbackConnector {
+    outgoing: Sender<Pipe>,
+}
+
+impl Connector for LoopbackConnector {
+    fn connect(&self) -> Result<Box<dyn Stream>, Error> {
+        let (client, server) = Pipe::new();
+
+        self.outgoing.send(server).map_err(|_| io::Error::new(
+            io::ErrorKind::ConnectionRefused, "loopback server has shut down"))?;
+
+        Ok(Box::new(client))
+    }
+}
+
+/* one end of a duplex byte channel, reads hit EOF once the other end is dropped */
+struct Pipe {
+    reader: PipeReader,
+    writer: PipeWriter,
+}
+
+struct PipeReader {
+    rx: Receiver<Vec<u8>>,
+    buf: Vec<u8>,
+    pos: usize,
+    timeout: Option<Duration>,
+}
+
+struct PipeWriter {
+    tx: Sender<Vec<u8>>,
+}
+
+impl Pipe {
+    fn new() -> (Pipe, Pipe) {
+        let (atx, arx) = mpsc::channel();
+        let (btx, brx) = mpsc::channel();
+
+        (Pipe::from(atx, brx), Pipe::from(btx, arx))
+    }
+
+    fn from(tx: Sender<Vec<u8>>, rx: Receiver<Vec<u8>>) -> Pipe {
+        Pipe {
+            reader: PipeReader {
+                rx: rx,
+                buf: vec![],
+                pos: 0,
+                timeout: None,
+            },
+            writer: PipeWriter {
+                tx: tx,
+            },
+        }
+    }
+}
+
+/* writes never block, so only reads can time out */
+impl Stream for Pipe {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.reader.timeout = timeout;
+        Ok(())
+    }
+
+    fn split(self: Box<Self>) -> Result<(ReadHalf, WriteHalf), Box<dyn Stream>> {
+        Ok((Box::new(self.reader), Box::new(self.writer)))
+    }
+}
+
+impl Reader for PipeReader {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+        if self.pos == self.buf.len() {
+            let next = match self.timeout {
+                Some(timeout) => self.rx.recv_timeout(timeout),
+                None => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match next {
+                Ok(x) => {
+                    self.buf = x;
+                    self.pos = 0;
+                },
+                Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(
+                    io::ErrorKind::TimedOut, "loopback peer did not respond in time")),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.buf.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+
+        /* an empty chunk would read as EOF on the other end */
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.tx.send(buf.to_vec()).map_err(|_| io::Error::new(
+            io::ErrorKind::BrokenPipe, "loopback peer hung up"))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
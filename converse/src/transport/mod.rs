@@ -0,0 +1,189 @@
+use std::io::{self, prelude::*};
+use std::net::SocketAddr;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::Error;
+
+pub mod unix;
+pub mod tcp;
+pub mod stdio;
+pub mod loopback;
+pub mod activation;
+#[cfg(feature = "tls")]
+pub mod tls;
+
+/*
+ * anything requests and responses can be framed over. Streams that can't
+ * time out keep the default implementations and block indefinitely.
+ */
+pub trait Stream: Read + Write + Send {
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /*
+     * Separate read and write halves, so responses can be written while the
+     * next request is being read. Dropping the write half signals EOF to the
+     * peer. Streams that can't be split hand themselves back.
+     */
+    fn split(self: Box<Self>) -> Result<(ReadHalf, WriteHalf), Box<dyn Stream>>;
+
+    /* a check for the peer having hung up, None if the transport can't tell */
+    fn hang_up(&self) -> Option<HangUp> {
+        None
+    }
+}
+
+/* the reading half of a split stream, timing out like the stream would */
+pub trait Reader: Read + Send {
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub type ReadHalf = Box<dyn Reader>;
+pub type WriteHalf = Box<dyn Write + Send>;
+pub type HangUp = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/* the unix and tcp streams, which split and watch for hang ups the same way */
+pub(crate) trait Socket: Stream + Reader + AsRawFd + Sync + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+
+    fn shutdown_write(&self) -> io::Result<()>;
+}
+
+/* Stream::split for sockets, the halves are clones of the one socket */
+pub(crate) fn split<S: Socket>(socket: Box<S>) -> Result<(ReadHalf, WriteHalf), Box<dyn Stream>> {
+    match socket.try_clone() {
+        Ok(reader) => Ok((Box::new(reader), Box::new(Writer(*socket)))),
+        Err(_) => Err(socket),
+    }
+}
+
+/* Stream::hang_up for sockets */
+pub(crate) fn hang_up<S: Socket>(socket: &S) -> Option<HangUp> {
+    let socket = socket.try_clone().ok()?;
+    Some(Arc::new(move || hung_up(socket.as_raw_fd())))
+}
+
+/* write half of a split socket, shuts down sending so the peer sees EOF */
+struct Writer<S: Socket>(S);
+
+impl<S: Socket> Write for Writer<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<S: Socket> Drop for Writer<S> {
+    fn drop(&mut self) {
+        self.0.shutdown_write().ok();
+    }
+}
+
+/*
+ * server side of a transport: hands out one stream per connection,
+ * or None once no more connections can arrive
+ */
+pub trait Listener: Send {
+    fn accept(&self) -> Result<Option<(Box<dyn Stream>, Peer)>, Error>;
+
+    /*
+     * like accept, but slow setup of the connection such as a TLS handshake
+     * is left to the returned function, which the server runs on the thread
+     * serving the connection so one client can't hold up accepting others
+     */
+    fn incoming(&self) -> Result<Option<Incoming>, Error> {
+        Ok(self.accept()?.map(|x| Box::new(move || Ok(x)) as Incoming))
+    }
+}
+
+impl<L: Listener + ?Sized> Listener for Box<L> {
+    fn accept(&self) -> Result<Option<(Box<dyn Stream>, Peer)>, Error> {
+        (**self).accept()
+    }
+
+    fn incoming(&self) -> Result<Option<Incoming>, Error> {
+        (**self).incoming()
+    }
+}
+
+/* a connection accepted by Listener::incoming, set up when called */
+pub type Incoming = Box<dyn FnOnce() -> Result<(Box<dyn Stream>, Peer), Error> + Send>;
+
+/* client side of a transport: opens a new stream for each call, or one shared by all */
+pub trait Connector: Send + Sync {
+    fn connect(&self) -> Result<Box<dyn Stream>, Error>;
+
+    fn connect_timeout(&self, _timeout: Duration) -> Result<Box<dyn Stream>, Error> {
+        self.connect()
+    }
+}
+
+/* who is on the other end of a connection, as seen by the server */
+#[derive(Clone, Debug)]
+pub enum Peer {
+    Unix(Option<Credentials>),
+    Tcp(SocketAddr),
+    Tls {
+        addr: SocketAddr,
+        /* DER encoded chain presented by the client, leaf first */
+        certificates: Vec<Vec<u8>>,
+    },
+    /* the parent process, see transport::stdio */
+    Stdio,
+    /* same process, see transport::loopback */
+    Loopback,
+}
+
+impl Peer {
+    /* on this machine, and trusted with shutting the server down */
+    pub fn is_local(&self) -> bool {
+        match self {
+            Peer::Unix(_) | Peer::Stdio | Peer::Loopback => true,
+            Peer::Tcp(_) | Peer::Tls { .. } => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub pid: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/* true once the peer closed a socket, peeks so pending requests stay unread */
+pub(crate) fn hung_up(fd: RawFd) -> bool {
+
+    let mut pfd = libc::pollfd {
+        fd: fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    if unsafe { libc::poll(&mut pfd, 1, 0) } <= 0 {
+        return false;
+    }
+
+    if pfd.revents & (libc::POLLHUP | libc::POLLERR) != 0 {
+        return true;
+    }
+
+    let mut buf = [0_u8; 1];
+    let n = unsafe {
+        libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, 1, libc::MSG_PEEK | libc::MSG_DONTWAIT)
+    };
+
+    n == 0
+}
@@ -0,0 +1,310 @@
+use std::fs::File;
+use std::io::{self, prelude::*};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::transport::{Connector, Listener, Peer, ReadHalf, Reader, Stream, WriteHalf};
+
+/*
+ * Serves a single connection made of this process' stdin and stdout.
+ * The original stdout is moved to a private descriptor and fd 1 is pointed
+ * at stderr, so a stray println! in a method can't corrupt the protocol.
+ */
+pub struct StdioListener {
+    stdout: Mutex<Option<File>>,
+}
+
+impl StdioListener {
+    pub fn new() -> Result<Self, Error> {
+
+        let stdout = unsafe {
+            let fd = libc::dup(libc::STDOUT_FILENO);
+            if fd < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            File::from_raw_fd(fd)
+        };
+
+        Ok(StdioListener {
+            stdout: Mutex::new(Some(stdout)),
+        })
+    }
+}
+
+impl Listener for StdioListener {
+    fn accept(&self) -> Result<Option<(Box<dyn Stream>, Peer)>, Error> {
+
+        /* once the parent hangs up there is nobody left to serve */
+        let stdout = match self.stdout.lock().unwrap().take() {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let stream = StdioStream {
+            stdin: io::stdin(),
+            stdout: stdout,
+        };
+
+        Ok(Some((Box::new(stream), Peer::Stdio)))
+    }
+}
+
+struct StdioStream {
+    stdin: io::Stdin,
+    stdout: File,
+}
+
+impl Stream for StdioStream {
+    fn split(self: Box<Self>) -> Result<(ReadHalf, WriteHalf), Box<dyn Stream>> {
+        Ok((Box::new(self.stdin), Box::new(self.stdout)))
+    }
+}
+
+/* blocks until the parent writes or hangs up */
+impl Reader for io::Stdin {}
+
+impl Read for StdioStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdin.read(buf)
+    }
+}
+
+impl Write for StdioStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+/*
+ * Talks to a child process over its stdin and stdout. There is only the one
+ * pair of pipes, so connections are lent them one at a time: connecting
+ * fails while another connection is still open. A connection failing, e.g.
+ * timing out with the response still on its way, closes the pipes for good
+ * as nothing read from them after would line up. Dropping the connector
+ * closes the child's stdin and reaps it, killing it if it does not exit on
+ * its own.
+ */
+pub struct ChildConnector {
+    child: Child,
+    pipes: Arc<Pipes>,
+}
+
+struct Pipes {
+    stdin: Mutex<Option<ChildStdin>>,
+    stdout: Mutex<Option<ChildStdout>>,
+    leased: AtomicBool,
+}
+
+/* held by both halves of a connection, the pipes are free once it's dropped */
+struct Lease(Arc<Pipes>);
+
+impl ChildConnector {
+    pub fn spawn(mut command: Command) -> Result<Self, Error> {
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let pipes = Pipes {
+            stdin: Mutex::new(child.stdin.take()),
+            stdout: Mutex::new(child.stdout.take()),
+            leased: AtomicBool::new(false),
+        };
+
+        Ok(ChildConnector {
+            child: child,
+            pipes: Arc::new(pipes),
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+}
+
+impl Connector for ChildConnector {
+    fn connect(&self) -> Result<Box<dyn Stream>, Error> {
+
+        if self.pipes.leased.swap(true, Ordering::SeqCst) {
+            return Err(Error::Client(format!("Child process is serving another connection")));
+        }
+
+        let lease = Arc::new(Lease(self.pipes.clone()));
+
+        Ok(Box::new(ChildStream {
+            reader: ChildReader {
+                lease: lease.clone(),
+                timeout: None,
+            },
+            writer: ChildWriter {
+                lease: lease,
+                timeout: None,
+            },
+        }))
+    }
+}
+
+impl Drop for ChildConnector {
+    fn drop(&mut self) {
+
+        /* EOF on stdin tells the server to return from run */
+        self.pipes.close();
+
+        for _ in 0..10 {
+            match self.child.try_wait() {
+                Ok(None) => thread::sleep(Duration::from_millis(50)),
+                _ => return,
+            }
+        }
+
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.0.leased.store(false, Ordering::SeqCst);
+    }
+}
+
+struct ChildStream {
+    reader: ChildReader,
+    writer: ChildWriter,
+}
+
+struct ChildReader {
+    lease: Arc<Lease>,
+    timeout: Option<Duration>,
+}
+
+struct ChildWriter {
+    lease: Arc<Lease>,
+    timeout: Option<Duration>,
+}
+
+impl Stream for ChildStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.reader.timeout = timeout;
+        Ok(())
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.writer.timeout = timeout;
+        Ok(())
+    }
+
+    fn split(self: Box<Self>) -> Result<(ReadHalf, WriteHalf), Box<dyn Stream>> {
+        Ok((Box::new(self.reader), Box::new(self.writer)))
+    }
+}
+
+impl Read for ChildStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Write for ChildStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Reader for ChildReader {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+
+impl Read for ChildReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = self.timeout;
+        self.lease.0.closing(with(&self.lease.0.stdout, |x| {
+            poll(x.as_raw_fd(), libc::POLLIN, timeout)?;
+            x.read(buf)
+        }))
+    }
+}
+
+impl Write for ChildWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let timeout = self.timeout;
+        self.lease.0.closing(with(&self.lease.0.stdin, |x| {
+            poll(x.as_raw_fd(), libc::POLLOUT, timeout)?;
+            x.write(buf)
+        }))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lease.0.closing(with(&self.lease.0.stdin, |x| x.flush()))
+    }
+}
+
+impl Pipes {
+    /* pass `result` on, closing the pipes if it's an error */
+    fn closing<R>(&self, result: io::Result<R>) -> io::Result<R> {
+
+        if result.is_err() {
+            self.close();
+        }
+
+        result
+    }
+
+    fn close(&self) {
+        self.stdin.lock().map(|mut x| x.take()).ok();
+
+        /* a split stream may be blocked reading, it sees EOF once the child exits */
+        self.stdout.try_lock().map(|mut x| x.take()).ok();
+    }
+}
+
+fn with<T, F, R>(pipe: &Mutex<Option<T>>, f: F) -> io::Result<R>
+where
+    F: FnOnce(&mut T) -> io::Result<R>
+{
+    let mut pipe = pipe.lock()
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "child pipes poisoned"))?;
+
+    match pipe.as_mut() {
+        Some(x) => f(x),
+        None => Err(io::Error::new(io::ErrorKind::BrokenPipe, "child process closed")),
+    }
+}
+
+/* pipes have no timeouts of their own, wait for readiness first */
+fn poll(fd: RawFd, events: libc::c_short, timeout: Option<Duration>) -> io::Result<()> {
+
+    let timeout = match timeout {
+        Some(x) => x.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+        None => return Ok(()),
+    };
+
+    let mut pfd = libc::pollfd {
+        fd: fd,
+        events: events,
+        revents: 0,
+    };
+
+    match unsafe { libc::poll(&mut pfd, 1, timeout) } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Err(io::Error::new(io::ErrorKind::TimedOut, "child process did not respond in time")),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,133 @@
+use std::io;
+use std::net::{self, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+use std::os::unix::io::{FromRawFd, RawFd};
+
+use crate::error::Error;
+use crate::transport::{self, Connector, HangUp, Listener, Peer, ReadHalf, Reader, Socket, Stream, WriteHalf};
+
+/*
+ * Connections have no read timeout, a client that stops sending holds its
+ * connection until it hangs up. Under run() that is the whole server.
+ */
+pub struct TcpListener {
+    socket: net::TcpListener,
+}
+
+impl TcpListener {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self, Error> {
+        Ok(TcpListener {
+            socket: net::TcpListener::bind(addr)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.socket.local_addr()?)
+    }
+}
+
+/* for a socket passed in by a service manager, see transport::activation */
+impl FromRawFd for TcpListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        TcpListener {
+            socket: net::TcpListener::from_raw_fd(fd),
+        }
+    }
+}
+
+impl Listener for TcpListener {
+    fn accept(&self) -> Result<Option<(Box<dyn Stream>, Peer)>, Error> {
+        let (stream, addr) = self.socket.accept()?;
+        stream.set_nodelay(true)?;
+
+        Ok(Some((Box::new(stream), Peer::Tcp(addr))))
+    }
+}
+
+pub struct TcpConnector {
+    addrs: Vec<SocketAddr>,
+}
+
+impl TcpConnector {
+    pub fn new<A: ToSocketAddrs>(addr: A) -> Result<Self, Error> {
+        Ok(TcpConnector {
+            addrs: resolve(addr)?,
+        })
+    }
+}
+
+impl Connector for TcpConnector {
+    fn connect(&self) -> Result<Box<dyn Stream>, Error> {
+        let stream = net::TcpStream::connect(&self.addrs[..])?;
+        stream.set_nodelay(true)?;
+
+        Ok(Box::new(stream))
+    }
+
+    fn connect_timeout(&self, timeout: Duration) -> Result<Box<dyn Stream>, Error> {
+        let stream = connect_timeout(&self.addrs, timeout)?;
+        stream.set_nodelay(true)?;
+
+        Ok(Box::new(stream))
+    }
+}
+
+impl Stream for net::TcpStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        net::TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        net::TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn split(self: Box<Self>) -> Result<(ReadHalf, WriteHalf), Box<dyn Stream>> {
+        transport::split(self)
+    }
+
+    fn hang_up(&self) -> Option<HangUp> {
+        transport::hang_up(self)
+    }
+}
+
+impl Reader for net::TcpStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        net::TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Socket for net::TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        net::TcpStream::try_clone(self)
+    }
+
+    fn shutdown_write(&self) -> io::Result<()> {
+        self.shutdown(net::Shutdown::Write)
+    }
+}
+
+/* try each address in turn like TcpStream::connect does */
+pub(crate) fn connect_timeout(addrs: &[SocketAddr], timeout: Duration) -> Result<net::TcpStream, Error> {
+
+    let mut last = None;
+
+    for addr in addrs {
+        match net::TcpStream::connect_timeout(addr, timeout) {
+            Ok(x) => return Ok(x),
+            Err(e) => last = Some(e),
+        }
+    }
+
+    Err(last.map(Error::from).unwrap_or_else(|| Error::Client(
+        format!("Address did not resolve to any socket address"))))
+}
+
+pub(crate) fn resolve<A: ToSocketAddrs>(addr: A) -> Result<Vec<SocketAddr>, Error> {
+    let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+
+    if addrs.is_empty() {
+        return Err(Error::Client(format!("Address did not resolve to any socket address")));
+    }
+
+    Ok(addrs)
+}
@@ -0,0 +1,304 @@
+use std::convert::TryFrom;
+use std::io::{self, prelude::*};
+use std::net::{self, SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::pki_types::pem::{self, PemObject};
+use rustls::server::WebPkiClientVerifier;
+
+use crate::error::Error;
+use crate::transport::{tcp, Connector, Incoming, Listener, Peer, ReadHalf, Stream, WriteHalf};
+
+/*
+ * Server configuration from PEM files. When `client_ca` is given every
+ * client must present a certificate signed by it (mutual TLS).
+ */
+pub fn server_config<P: AsRef<Path>>(cert: P, key: P, client_ca: Option<P>) -> Result<Arc<ServerConfig>, Error> {
+
+    let builder = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?;
+
+    let builder = match client_ca {
+        Some(ca) => {
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots(ca)?), provider())
+                .build()
+                .map_err(tls_error)?;
+            builder.with_client_cert_verifier(verifier)
+        },
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder.with_single_cert(certs(cert)?, private_key(key)?)
+        .map_err(tls_error)?;
+
+    Ok(Arc::new(config))
+}
+
+/*
+ * Client configuration from PEM files. `identity` is the certificate and
+ * key pair to present when the server requires client authentication.
+ */
+pub fn client_config<P: AsRef<Path>>(ca: P, identity: Option<(P, P)>) -> Result<Arc<ClientConfig>, Error> {
+
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?
+        .with_root_certificates(roots(ca)?);
+
+    let config = match identity {
+        Some((cert, key)) => builder.with_client_auth_cert(certs(cert)?, private_key(key)?)
+            .map_err(tls_error)?,
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(Arc::new(config))
+}
+
+/*
+ * How long a client gets to complete the handshake. run_concurrent shakes
+ * hands on the thread serving the connection, run() does so in accept and
+ * waits on a silent client for this long before serving the next.
+ */
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/*
+ * Sent by the server once it accepted the client. Under TLS 1.3 the client
+ * finishes its side of the handshake before the server has checked its
+ * certificate, waiting for this makes a rejection show up in connect.
+ */
+const ACCEPTED: u8 = 1;
+
+/*
+ * After the handshake nothing times out, a client that stops sending holds
+ * a connection until it hangs up. Under run() that is the whole server.
+ */
+pub struct TlsListener {
+    socket: net::TcpListener,
+    config: Arc<ServerConfig>,
+    handshake: Duration,
+}
+
+impl TlsListener {
+    pub fn bind<A: ToSocketAddrs>(addr: A, config: Arc<ServerConfig>) -> Result<Self, Error> {
+        Ok(TlsListener {
+            socket: net::TcpListener::bind(addr)?,
+            config: config,
+            handshake: HANDSHAKE_TIMEOUT,
+        })
+    }
+
+    /* how long a client gets to complete the handshake, HANDSHAKE_TIMEOUT by default */
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake = timeout;
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.socket.local_addr()?)
+    }
+}
+
+impl Listener for TlsListener {
+    fn accept(&self) -> Result<Option<(Box<dyn Stream>, Peer)>, Error> {
+        match self.incoming()? {
+            Some(incoming) => incoming().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn incoming(&self) -> Result<Option<Incoming>, Error> {
+
+        let (stream, addr) = self.socket.accept()?;
+        let (config, timeout) = (self.config.clone(), self.handshake);
+
+        Ok(Some(Box::new(move || accept(stream, addr, config, timeout))))
+    }
+}
+
+/* the server's side of the handshake on an accepted connection */
+fn accept(mut stream: net::TcpStream, addr: SocketAddr, config: Arc<ServerConfig>, timeout: Duration)
+    -> Result<(Box<dyn Stream>, Peer), Error>
+{
+    let mut conn = ServerConnection::new(config).map_err(tls_error)?;
+
+    /* whatever goes wrong here is down to this one client */
+    let mut handshake = || -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)?;
+        }
+
+        conn.writer().write_all(&[ACCEPTED])?;
+        while conn.wants_write() {
+            conn.write_tls(&mut stream)?;
+        }
+
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)
+    };
+
+    handshake().map_err(|e| Error::Handshake(
+        format!("TLS handshake with '{}' failed: {}", addr, e)))?;
+
+    let certificates = conn.peer_certificates()
+        .map(|chain| chain.iter().map(|x| x.as_ref().to_vec()).collect())
+        .unwrap_or_default();
+
+    let peer = Peer::Tls {
+        addr: addr,
+        certificates: certificates,
+    };
+
+    Ok((Box::new(StreamOwned::new(conn, stream)), peer))
+}
+
+pub struct TlsConnector {
+    addrs: Vec<SocketAddr>,
+    name: ServerName<'static>,
+    config: Arc<ClientConfig>,
+}
+
+impl TlsConnector {
+    /* `name` is the DNS name or IP address the server certificate is checked against */
+    pub fn new<A: ToSocketAddrs>(addr: A, name: &str, config: Arc<ClientConfig>) -> Result<Self, Error> {
+
+        let name = ServerName::try_from(name.to_string())
+            .map_err(|e| Error::Tls(format!("Invalid server name '{}': {}", name, e)))?;
+
+        Ok(TlsConnector {
+            addrs: tcp::resolve(addr)?,
+            name: name,
+            config: config,
+        })
+    }
+}
+
+impl TlsConnector {
+    /*
+     * Shake hands and wait for the server to accept us. Any failure, an
+     * alert from the server included, is a Handshake error, which isn't
+     * retried like a connection failure.
+     */
+    fn handshake(&self, stream: net::TcpStream) -> Result<Box<dyn Stream>, Error> {
+
+        let addr = stream.peer_addr()?;
+        let failed = |e: io::Error| Error::Handshake(format!("TLS handshake with '{}' failed: {}", addr, e));
+
+        stream.set_nodelay(true).map_err(failed)?;
+
+        let conn = ClientConnection::new(self.config.clone(), self.name.clone())
+            .map_err(tls_error)?;
+
+        let mut stream = StreamOwned::new(conn, stream);
+
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock).map_err(failed)?;
+        }
+
+        let mut accepted = [0_u8; 1];
+        match stream.read_exact(&mut accepted) {
+            Ok(()) if accepted[0] == ACCEPTED => Ok(Box::new(stream)),
+            Ok(()) => Err(Error::Handshake(format!("TLS handshake with '{}' failed: unexpected reply", addr))),
+            Err(e) => Err(failed(e)),
+        }
+    }
+}
+
+impl Connector for TlsConnector {
+    fn connect(&self) -> Result<Box<dyn Stream>, Error> {
+
+        /* a server that never answers would hang the client otherwise */
+        let stream = net::TcpStream::connect(&self.addrs[..])?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+        self.handshake(stream)
+    }
+
+    fn connect_timeout(&self, timeout: Duration) -> Result<Box<dyn Stream>, Error> {
+
+        /* bound the handshake too, the caller resets these per call */
+        let stream = tcp::connect_timeout(&self.addrs, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        self.handshake(stream)
+    }
+}
+
+impl<C: Send + 'static> Stream for StreamOwned<C, net::TcpStream>
+where
+    StreamOwned<C, net::TcpStream>: io::Read + io::Write
+{
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_write_timeout(timeout)
+    }
+
+    /* one session can't be driven from two threads */
+    fn split(self: Box<Self>) -> Result<(ReadHalf, WriteHalf), Box<dyn Stream>> {
+        Err(self)
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(crypto::ring::default_provider())
+}
+
+fn certs<P: AsRef<Path>>(path: P) -> Result<Vec<CertificateDer<'static>>, Error> {
+
+    let certs = CertificateDer::pem_file_iter(path.as_ref())
+        .and_then(|x| x.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| pem_error(path.as_ref(), e))?;
+
+    if certs.is_empty() {
+        return Err(Error::Tls(
+            format!("No certificates found in '{}'", path.as_ref().display())))
+    }
+
+    Ok(certs)
+}
+
+fn private_key<P: AsRef<Path>>(path: P) -> Result<PrivateKeyDer<'static>, Error> {
+    match PrivateKeyDer::from_pem_file(path.as_ref()) {
+        Ok(key) => Ok(key),
+        Err(pem::Error::NoItemsFound) => Err(Error::Tls(
+            format!("No private key found in '{}'", path.as_ref().display()))),
+        Err(e) => Err(pem_error(path.as_ref(), e)),
+    }
+}
+
+fn roots<P: AsRef<Path>>(path: P) -> Result<RootCertStore, Error> {
+
+    let mut roots = RootCertStore::empty();
+
+    for cert in certs(path)? {
+        roots.add(cert).map_err(tls_error)?;
+    }
+
+    Ok(roots)
+}
+
+/* failing to open the file is reported as such */
+fn pem_error(path: &Path, e: pem::Error) -> Error {
+    match e {
+        pem::Error::Io(e) => Error::from(e),
+        e => Error::Tls(format!("Invalid PEM file '{}': {}", path.display(), e)),
+    }
+}
+
+fn tls_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::Tls(e.to_string())
+}
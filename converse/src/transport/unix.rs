@@ -0,0 +1,158 @@
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net;
+use std::path::Path;
+use std::io;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::transport::{self, Connector, Credentials, HangUp, Listener, Peer, ReadHalf, Reader, Socket, Stream, WriteHalf};
+
+pub struct UnixListener {
+    socket: net::UnixListener,
+}
+
+impl UnixListener {
+    pub fn bind<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(UnixListener {
+            socket: net::UnixListener::bind(path)?,
+        })
+    }
+
+    /*
+     * Bind in the linux abstract namespace. Nothing is created on the
+     * filesystem and the name is released when the socket is closed.
+     */
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_abstract<N: AsRef<[u8]>>(name: N) -> Result<Self, Error> {
+        let addr = abstract_addr(name.as_ref())?;
+
+        Ok(UnixListener {
+            socket: net::UnixListener::bind_addr(&addr)?,
+        })
+    }
+}
+
+/* adopt an already bound and listening socket, see transport::activation */
+impl FromRawFd for UnixListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        UnixListener {
+            socket: net::UnixListener::from_raw_fd(fd),
+        }
+    }
+}
+
+impl Listener for UnixListener {
+    fn accept(&self) -> Result<Option<(Box<dyn Stream>, Peer)>, Error> {
+        let (stream, _) = self.socket.accept()?;
+        let peer = Peer::Unix(credentials(&stream));
+
+        Ok(Some((Box::new(stream), peer)))
+    }
+}
+
+impl Stream for net::UnixStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        net::UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        net::UnixStream::set_write_timeout(self, timeout)
+    }
+
+    fn split(self: Box<Self>) -> Result<(ReadHalf, WriteHalf), Box<dyn Stream>> {
+        transport::split(self)
+    }
+
+    fn hang_up(&self) -> Option<HangUp> {
+        transport::hang_up(self)
+    }
+}
+
+impl Reader for net::UnixStream {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        net::UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Socket for net::UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        net::UnixStream::try_clone(self)
+    }
+
+    fn shutdown_write(&self) -> io::Result<()> {
+        self.shutdown(std::net::Shutdown::Write)
+    }
+}
+
+pub struct UnixConnector {
+    addr: net::SocketAddr,
+}
+
+impl UnixConnector {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(UnixConnector {
+            addr: net::SocketAddr::from_pathname(path)?,
+        })
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn new_abstract<N: AsRef<[u8]>>(name: N) -> Result<Self, Error> {
+        Ok(UnixConnector {
+            addr: abstract_addr(name.as_ref())?,
+        })
+    }
+}
+
+impl Connector for UnixConnector {
+    fn connect(&self) -> Result<Box<dyn Stream>, Error> {
+        Ok(Box::new(net::UnixStream::connect_addr(&self.addr)?))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn abstract_addr(name: &[u8]) -> Result<net::SocketAddr, Error> {
+    #[cfg(target_os = "linux")]
+    use std::os::linux::net::SocketAddrExt;
+    #[cfg(target_os = "android")]
+    use std::os::android::net::SocketAddrExt;
+
+    if name.is_empty() {
+        return Err(Error::Server(format!("Abstract socket name must not be empty")));
+    }
+
+    Ok(net::SocketAddr::from_abstract_name(name)?)
+}
+
+/* SO_PEERCRED is only available on linux */
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn credentials(stream: &net::UnixStream) -> Option<Credentials> {
+    use std::mem;
+
+    let mut cred: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    if ret != 0 {
+        return None;
+    }
+
+    Some(Credentials {
+        pid: cred.pid as u32,
+        uid: cred.uid,
+        gid: cred.gid,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn credentials(_: &net::UnixStream) -> Option<Credentials> {
+    None
+}
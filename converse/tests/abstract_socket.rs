@@ -0,0 +1,50 @@
+/* a server in the linux abstract namespace, found by name alone */
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use std::process;
+use std::thread;
+
+use converse::context;
+use converse::error::Error;
+use converse::transport::Peer;
+use converse::transport::unix::UnixListener;
+use converse_derive::Converse;
+
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_abstract)]
+impl Counter {
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    /* pid of the caller, from the socket's credentials */
+    pub fn caller(&self) -> Option<u32> {
+        match context::current().map(|x| x.peer().clone()) {
+            Some(Peer::Unix(Some(credentials))) => Some(credentials.pid),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn abstract_socket() -> Result<(), Error> {
+
+    let mut server = Counter { count: 0 }.server_abstract()?;
+    thread::spawn(move || server.run());
+
+    let mut client = Counter::client_abstract()?;
+
+    assert_eq!(client.add(2)?, 2);
+    assert_eq!(client.add(3)?, 5);
+    assert_eq!(client.caller()?, Some(process::id()));
+
+    /* the name is taken while the server has it */
+    assert!(UnixListener::bind_abstract("converse_test_abstract").is_err());
+    assert!(UnixListener::bind_abstract("").is_err());
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+/* sockets handed over by a service manager through LISTEN_FDS */
+mod common;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net;
+use std::os::unix::process::CommandExt;
+use std::process;
+
+use converse::error::Error;
+use converse::transport::activation::{self, LISTEN_FDS_START};
+use converse::transport::unix::UnixConnector;
+use converse_derive::Converse;
+
+use common::{check, child};
+
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_activation)]
+impl Counter {
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    pub fn pid(&self) -> u32 {
+        process::id()
+    }
+}
+
+fn main() {
+    common::main(serve, &[
+        ("activated", activated),
+        ("other_process", other_process),
+        ("invalid", invalid),
+    ]);
+}
+
+fn serve(_: &str) -> Result<(), Error> {
+    /* a service manager sets this between fork and exec, when the pid is known */
+    env::set_var("LISTEN_PID", process::id().to_string());
+    Counter { count: 0 }.server_activated()?.run()
+}
+
+/* the socket is bound here and passed to the child as fd 3, as systemd would */
+fn activated() -> Result<(), Error> {
+    let path = env::temp_dir().join(format!("converse_test_activated_{}.sock", process::id()));
+    fs::remove_file(&path).ok();
+
+    let listener = net::UnixListener::bind(&path)?;
+    let fd = listener.as_raw_fd();
+
+    let mut command = child("activated")?;
+    command.env("LISTEN_FDS", "1");
+    unsafe {
+        command.pre_exec(move || {
+            /* dup2 onto itself would keep close-on-exec */
+            let ret = match fd == LISTEN_FDS_START {
+                true => libc::fcntl(fd, libc::F_SETFD, 0),
+                false => libc::dup2(fd, LISTEN_FDS_START),
+            };
+            match ret < 0 {
+                true => Err(io::Error::last_os_error()),
+                false => Ok(()),
+            }
+        });
+    }
+
+    let mut server = command.spawn()?;
+    drop(listener);
+
+    let result = (|| {
+        let mut client = Counter::client_with(UnixConnector::new(&path)?)?;
+
+        check(client.add(4)? == 4, "add")?;
+        check(client.pid()? == server.id(), "served by the child")?;
+
+        /* the peer is local, so exit is honoured */
+        client.exit().ok();
+        let status = server.wait()?;
+        check(status.code() == Some(0), "child exited cleanly")
+    })();
+
+    server.kill().ok();
+    server.wait().ok();
+    fs::remove_file(&path).ok();
+
+    result
+}
+
+/* meant for whoever started us, and cleared either way */
+fn other_process() -> Result<(), Error> {
+    env::set_var("LISTEN_PID", "1");
+    env::set_var("LISTEN_FDS", "1");
+
+    check(activation::listen_fds()?.is_empty(), "sockets taken")?;
+    check(env::var("LISTEN_FDS").is_err(), "LISTEN_FDS kept")
+}
+
+fn invalid() -> Result<(), Error> {
+    for fds in ["-1", "2147483647", "three"].iter() {
+        env::set_var("LISTEN_PID", process::id().to_string());
+        env::set_var("LISTEN_FDS", fds);
+
+        check(activation::listen_fds().is_err(), fds)?;
+    }
+
+    Ok(())
+}
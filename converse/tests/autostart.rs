@@ -0,0 +1,102 @@
+/* clients starting the server when nobody is listening yet */
+mod common;
+
+use std::env;
+use std::fs;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::autostart::AutoStart;
+use converse::error::Error;
+use converse_derive::Converse;
+
+use common::{check, child};
+
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_autostart)]
+impl Counter {
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    pub fn pid(&self) -> u32 {
+        process::id()
+    }
+}
+
+fn main() {
+    /* keep the process directory to ourselves, the children inherit this */
+    let tmp = env::temp_dir().join(format!("converse_test_autostart_{}", process::id()));
+    if env::var(common::SERVE).is_err() {
+        fs::create_dir_all(&tmp).unwrap();
+        env::set_var("TMPDIR", &tmp);
+    }
+
+    common::main(serve, &[
+        ("exited", exited),
+        ("timeout", timeout),
+        ("start", start),
+    ]);
+
+    fs::remove_dir_all(&tmp).ok();
+}
+
+fn serve(what: &str) -> Result<(), Error> {
+    match what {
+        "server" => Counter { count: 0 }.server()?.run(),
+        "hang" => loop { thread::sleep(Duration::from_secs(1)) },
+        x => Err(Error::Server(format!("Told to fail: {}", x))),
+    }
+}
+
+fn exited() -> Result<(), Error> {
+    let mut command = child("fail")?;
+    command.stderr(process::Stdio::null());
+
+    match Counter::client_autostart(AutoStart::new(command)) {
+        Err(Error::Client(e)) => check(e.contains("exited"), &e),
+        x => Err(Error::Client(format!("expected the start to fail, got {:?}", x.map(|_| ())))),
+    }
+}
+
+fn timeout() -> Result<(), Error> {
+    let autostart = AutoStart::new(child("hang")?).timeout(Duration::from_millis(200));
+    let start = Instant::now();
+
+    match Counter::client_autostart(autostart) {
+        Err(Error::Client(e)) => check(e.contains("Timed out"), &e)?,
+        x => return Err(Error::Client(format!("expected the start to time out, got {:?}", x.map(|_| ())))),
+    }
+
+    check(start.elapsed() < Duration::from_secs(2), "waited too long")
+}
+
+/* the first client starts the server, the next finds it running */
+fn start() -> Result<(), Error> {
+    let mut first = Counter::client_autostart(AutoStart::new(child("server")?))?;
+
+    check(first.add(1)? == 1, "add")?;
+    let pid = first.pid()?;
+    check(pid != process::id(), "served by this process")?;
+
+    let mut second = Counter::client_autostart(AutoStart::new(child("server")?))?;
+
+    check(second.add(1)? == 2, "second add")?;
+    check(second.pid()? == pid, "a second server started")?;
+
+    second.exit()?;
+
+    /* the server removes its socket on the way out */
+    let socket = env::temp_dir().join("converse_test_autostart").join("socket");
+    let start = Instant::now();
+    while socket.exists() && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    check(!socket.exists(), "server still running")
+}
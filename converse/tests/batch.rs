@@ -0,0 +1,103 @@
+/* several calls in one round trip */
+use std::thread;
+
+use converse::client::Channel;
+use converse::error::Error;
+use converse::protocol::{self, IPCRequest, IPCResponse};
+use converse::transport::loopback::{self, LoopbackConnector};
+use converse_derive::Converse;
+
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_batch)]
+impl Counter {
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn describe(&self, prefix: String) -> String {
+        format!("{}{}", prefix, self.count)
+    }
+}
+
+fn serve() -> Result<LoopbackConnector, Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Counter { count: 0 }.server_with(listener)?;
+
+    thread::spawn(move || server.run_concurrent());
+
+    Ok(connector)
+}
+
+/* the responses to the calls of a batch sent as is */
+fn send(connector: &LoopbackConnector, requests: Vec<IPCRequest>) -> Result<Vec<IPCResponse>, Error> {
+    let channel = Channel::new(Box::new(connector.clone()));
+    IPCResponse::unpack(&channel.call(protocol::BATCH, IPCRequest::pack(requests)?)?)
+}
+
+#[test]
+fn batch() -> Result<(), Error> {
+    let client = Counter::client_with(serve()?)?;
+
+    let (a, b, count, description) = client.batch()
+        .add(1)
+        .add(10)
+        .count()
+        .describe("=".to_string())
+        .send()?;
+
+    assert_eq!((a, b, count), (1, 11, 11));
+    assert_eq!(description, "=11");
+
+    Ok(())
+}
+
+/* a batch in a batch is refused, however deep, instead of recursing */
+#[test]
+fn nested() -> Result<(), Error> {
+    let connector = serve()?;
+
+    let mut inner = IPCRequest::new(protocol::BATCH, vec![]);
+    for _ in 0..10_000 {
+        inner = IPCRequest::new(protocol::BATCH, IPCRequest::pack(vec![inner])?);
+    }
+
+    let responses = send(&connector, vec![inner])?;
+    assert_eq!(responses.len(), 1);
+
+    match responses.into_iter().next().unwrap().into_result() {
+        Err(Error::Server(e)) => assert!(e.contains("can't be batched"), "{}", e),
+        x => panic!("expected the nested batch to be refused, got {:?}", x),
+    }
+
+    /* and the server is still up */
+    let client = Counter::client_with(connector)?;
+    assert_eq!(client.count()?, 0);
+
+    Ok(())
+}
+
+/* transactions are begun and ended outside of batches */
+#[test]
+fn control() -> Result<(), Error> {
+    let connector = serve()?;
+
+    for key in &[protocol::BEGIN, protocol::COMMIT, protocol::ABORT, protocol::CANCEL] {
+        let responses = send(&connector, vec![IPCRequest::new(*key, vec![])])?;
+
+        match responses.into_iter().next().map(IPCResponse::into_result) {
+            Some(Err(Error::Server(e))) => assert!(e.contains("can't be batched"), "{}", e),
+            x => panic!("expected {} to be refused, got {:?}", key, x),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+/* calls the client gave up on stop running on the server */
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::context;
+use converse::error::Error;
+use converse::transport::loopback;
+use converse_derive::Converse;
+
+struct Spinner {
+    cancelled: AtomicU32,
+}
+
+#[Converse(converse_test_cancel)]
+impl Spinner {
+    /* runs until cancelled, or gives up after a while */
+    pub fn spin(&self) -> bool {
+        let start = Instant::now();
+
+        while start.elapsed() < Duration::from_secs(5) {
+            if context::current().map(|x| x.cancelled()).unwrap_or(false) {
+                self.cancelled.fetch_add(1, Ordering::SeqCst);
+                return true;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        false
+    }
+
+    pub fn cancelled(&self) -> u32 {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/* give up on a spin and wait for the server to notice */
+fn give_up(client: &mut SpinnerClient) -> Result<(), Error> {
+    client.timeouts().read = Some(Duration::from_millis(100));
+
+    match client.spin() {
+        Err(Error::Timeout(_)) => {},
+        x => panic!("expected a timeout, got {:?}", x),
+    }
+
+    client.timeouts().read = None;
+
+    for _ in 0..200 {
+        if client.cancelled()? == 1 {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    panic!("the server kept spinning");
+}
+
+/* a multiplexed client sends a cancellation for the call */
+#[test]
+fn cancelled() -> Result<(), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Spinner { cancelled: AtomicU32::new(0) }.server_with(listener)?;
+    thread::spawn(move || server.run_concurrent());
+
+    let mut client = Spinner::client_with(connector)?;
+    client.multiplex()?;
+
+    give_up(&mut client)
+}
+
+/* any other client hangs up, which the server watches sockets for */
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn hung_up() -> Result<(), Error> {
+
+    let mut server = Spinner { cancelled: AtomicU32::new(0) }.server_abstract()?;
+    thread::spawn(move || server.run_concurrent());
+
+    give_up(&mut Spinner::client_abstract()?)
+}
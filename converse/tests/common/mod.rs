@@ -0,0 +1,59 @@
+/*
+ * Tests serving from a child process. Their binaries have no test harness,
+ * main runs the tests, and runs the test binary again with SERVE set to
+ * what the child should serve.
+ */
+#![allow(dead_code)]
+
+use std::env;
+use std::process::{self, Command};
+
+use converse::error::Error;
+
+pub const SERVE: &str = "CONVERSE_TEST_SERVE";
+
+pub type Test = fn() -> Result<(), Error>;
+
+/* serve as told by SERVE in a child, run `tests` otherwise */
+pub fn main(serve: fn(&str) -> Result<(), Error>, tests: &[(&str, Test)]) {
+
+    if let Ok(what) = env::var(SERVE) {
+        match serve(&what) {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("{}: {}", what, e);
+                process::exit(2);
+            },
+        }
+    }
+
+    let mut failed = 0;
+
+    for (name, test) in tests {
+        match test() {
+            Ok(()) => println!("test {} ... ok", name),
+            Err(e) => {
+                println!("test {} ... FAILED: {}", name, e);
+                failed += 1;
+            },
+        }
+    }
+
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/* the test binary, serving `what` */
+pub fn child(what: &str) -> Result<Command, Error> {
+    let mut command = Command::new(env::current_exe()?);
+    command.env(SERVE, what);
+    Ok(command)
+}
+
+pub fn check(ok: bool, message: &str) -> Result<(), Error> {
+    match ok {
+        true => Ok(()),
+        false => Err(Error::Client(message.to_string())),
+    }
+}
@@ -0,0 +1,89 @@
+/* a server detaching into the background with daemonize */
+mod common;
+
+use std::env;
+use std::fs;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::error::Error;
+use converse_derive::Converse;
+
+use common::{check, child};
+
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_daemon)]
+impl Counter {
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    /* pid and session id of the server */
+    pub fn ids(&self) -> (u32, i32) {
+        (process::id(), unsafe { libc::getsid(0) })
+    }
+
+    pub fn note(&self, x: String) {
+        eprintln!("{}", x);
+    }
+}
+
+fn main() {
+    /* keep the process directory to ourselves, the children inherit this */
+    let tmp = env::temp_dir().join(format!("converse_test_daemon_{}", process::id()));
+    if env::var(common::SERVE).is_err() {
+        fs::create_dir_all(&tmp).unwrap();
+        env::set_var("TMPDIR", &tmp);
+    }
+
+    common::main(serve, &[
+        ("daemonize", daemonize),
+    ]);
+
+    fs::remove_dir_all(&tmp).ok();
+}
+
+fn serve(_: &str) -> Result<(), Error> {
+    let log = env::temp_dir().join("log");
+    Counter { count: 0 }.server()?.daemonize(log)?.run()
+}
+
+fn daemonize() -> Result<(), Error> {
+
+    /* the launcher only exits once the daemon is ready */
+    let mut launcher = child("daemon")?.spawn()?;
+    let status = launcher.wait()?;
+    check(status.success(), "launcher failed")?;
+
+    let mut client = Counter::client()?;
+
+    check(client.add(2)? == 2, "add")?;
+
+    let (pid, session) = client.ids()?;
+    check(pid != launcher.id() && pid != process::id(), "served by the launcher")?;
+    check(session != unsafe { libc::getsid(0) }, "still in our session")?;
+
+    /* the daemon took over the lockfile */
+    let proc = env::temp_dir().join("converse_test_daemon");
+    let locked = fs::read_to_string(proc.join("lock"))?;
+    check(locked == pid.to_string(), "lockfile not taken over")?;
+
+    /* output goes to the log */
+    client.note("written by the daemon".to_string())?;
+    let log = fs::read_to_string(env::temp_dir().join("log"))?;
+    check(log.contains("written by the daemon"), "nothing in the log")?;
+
+    client.exit()?;
+
+    let start = Instant::now();
+    while proc.join("socket").exists() && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    check(!proc.join("socket").exists(), "daemon still running")
+}
@@ -0,0 +1,68 @@
+/* a client and server in one process, talking over transport::loopback */
+use std::thread;
+
+use converse::error::Error;
+use converse::transport::loopback;
+use converse_derive::Converse;
+
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_loopback)]
+impl Counter {
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn describe(&self, prefix: String) -> String {
+        format!("{}{}", prefix, self.count)
+    }
+}
+
+fn serve() -> Result<CounterClient, Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Counter { count: 0 }.server_with(listener)?;
+
+    thread::spawn(move || server.run_concurrent());
+
+    Counter::client_with(connector)
+}
+
+#[test]
+fn round_trip() -> Result<(), Error> {
+    let mut client = serve()?;
+
+    assert_eq!(client.add(2)?, 2);
+    assert_eq!(client.add(3)?, 5);
+    assert_eq!(client.count()?, 5);
+    assert_eq!(client.describe("count: ".to_string())?, "count: 5");
+
+    Ok(())
+}
+
+/* the server runs until every connector is gone */
+#[test]
+fn shut_down() -> Result<(), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Counter { count: 0 }.server_with(listener)?;
+    let running = thread::spawn(move || server.run());
+
+    let mut client = Counter::client_with(connector.clone())?;
+    assert_eq!(client.add(1)?, 1);
+
+    drop(client);
+    assert!(!running.is_finished());
+
+    drop(connector);
+    running.join().unwrap()?;
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+/* concurrent calls over one connection, answered as they finish */
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::error::Error;
+use converse::transport::{Connector, Stream};
+use converse::transport::loopback::{self, LoopbackConnector};
+use converse_derive::Converse;
+
+struct Sleeper {
+    woken: Mutex<Vec<u64>>,
+}
+
+#[Converse(converse_test_multiplex)]
+impl Sleeper {
+    /* holds the state shared, so sleepers overlap */
+    pub fn sleep(&self, ms: u64) -> u64 {
+        thread::sleep(Duration::from_millis(ms));
+        ms
+    }
+
+    /* calls taking the state shared can still change it */
+    pub fn woke(&self, ms: u64) -> usize {
+        let mut woken = self.woken.lock().unwrap();
+        woken.push(ms);
+        woken.len()
+    }
+
+    pub fn woken(&self) -> Vec<u64> {
+        self.woken.lock().unwrap().clone()
+    }
+}
+
+/* counts the connections opened */
+struct Counting {
+    inner: LoopbackConnector,
+    connections: Arc<AtomicU32>,
+}
+
+impl Connector for Counting {
+    fn connect(&self) -> Result<Box<dyn Stream>, Error> {
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        self.inner.connect()
+    }
+}
+
+fn serve(calls: usize) -> Result<(Arc<SleeperClient>, Arc<AtomicU32>), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Sleeper { woken: Mutex::new(vec![]) }.server_with(listener)?.max_calls(calls);
+    thread::spawn(move || server.run_concurrent());
+
+    let connections = Arc::new(AtomicU32::new(0));
+    let mut client = Sleeper::client_with(Counting {
+        inner: connector,
+        connections: connections.clone(),
+    })?;
+    client.multiplex()?;
+
+    Ok((Arc::new(client), connections))
+}
+
+/* sleep for each of `ms` on its own thread, returns how long that took */
+fn sleep_all(client: &Arc<SleeperClient>, ms: &[u64]) -> Result<Duration, Error> {
+    let start = Instant::now();
+
+    let threads = ms.iter().map(|&ms| {
+        let client = client.clone();
+        thread::spawn(move || -> Result<usize, Error> {
+            let slept = client.sleep(ms)?;
+            client.woke(slept)
+        })
+    }).collect::<Vec<_>>();
+
+    for x in threads {
+        x.join().unwrap()?;
+    }
+
+    Ok(start.elapsed())
+}
+
+#[test]
+fn overlap() -> Result<(), Error> {
+    let (client, connections) = serve(64)?;
+
+    let elapsed = sleep_all(&client, &[200, 200, 200, 200])?;
+    assert!(elapsed < Duration::from_millis(600), "took {:?}", elapsed);
+
+    assert_eq!(client.woken()?.len(), 4);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+/* a fast call sent after a slow one isn't answered after it */
+#[test]
+fn out_of_order() -> Result<(), Error> {
+    let (client, _) = serve(64)?;
+
+    let slow = {
+        let client = client.clone();
+        thread::spawn(move || client.sleep(300).and_then(|x| client.woke(x)))
+    };
+
+    thread::sleep(Duration::from_millis(50));
+    let fast = client.sleep(10)?;
+    client.woke(fast)?;
+
+    slow.join().unwrap()?;
+    assert_eq!(client.woken()?, vec![10, 300]);
+
+    Ok(())
+}
+
+/* the server runs no more calls of a connection at once than it was told */
+#[test]
+fn max_calls() -> Result<(), Error> {
+    let (client, _) = serve(2)?;
+
+    let elapsed = sleep_all(&client, &[200, 200, 200, 200])?;
+    assert!(elapsed >= Duration::from_millis(400), "took {:?}", elapsed);
+
+    Ok(())
+}
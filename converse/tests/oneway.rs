@@ -0,0 +1,67 @@
+/* oneway calls return without waiting for the server */
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::error::Error;
+use converse::transport::loopback;
+use converse_derive::Converse;
+
+struct Log {
+    lines: Vec<String>,
+}
+
+#[Converse(converse_test_oneway)]
+impl Log {
+    #[converse(oneway)]
+    pub fn log(&mut self, x: String) {
+        self.lines.push(x);
+    }
+
+    #[converse(oneway)]
+    pub fn slow(&mut self, ms: u64) {
+        thread::sleep(Duration::from_millis(ms));
+        self.lines.push("slow".to_string());
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.clone()
+    }
+}
+
+fn serve() -> Result<LogClient, Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Log { lines: vec![] }.server_with(listener)?;
+
+    thread::spawn(move || server.run());
+
+    Log::client_with(connector)
+}
+
+#[test]
+fn oneway() -> Result<(), Error> {
+    let mut client = serve()?;
+
+    client.log("first".to_string())?;
+    client.log("second".to_string())?;
+
+    /* calls on a connection are served in order, so no need to poll */
+    assert_eq!(client.lines()?, vec!["first".to_string(), "second".to_string()]);
+
+    Ok(())
+}
+
+#[test]
+fn returns_immediately() -> Result<(), Error> {
+    let mut client = serve()?;
+    let start = Instant::now();
+
+    client.slow(300)?;
+    assert!(start.elapsed() < Duration::from_millis(200), "waited {:?}", start.elapsed());
+
+    /* and the server still ran it */
+    assert_eq!(client.lines()?, vec!["slow".to_string()]);
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+/* what a panicking method does to the server, per PanicPolicy */
+mod common;
+
+use std::env;
+use std::fs;
+use std::panic;
+use std::process::{self, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::error::Error;
+use converse::server::PanicPolicy;
+use converse::transport::loopback;
+use converse::transport::stdio::StdioListener;
+use converse_derive::Converse;
+
+use common::{check, child};
+
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_panic)]
+impl Counter {
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn fail(&mut self) -> u64 {
+        self.count += 1;
+        panic!("fail was called");
+    }
+}
+
+fn main() {
+    /* keep the process directory to ourselves, the children inherit this */
+    let tmp = env::temp_dir().join(format!("converse_test_panic_{}", process::id()));
+    if env::var(common::SERVE).is_err() {
+        fs::create_dir_all(&tmp).unwrap();
+        env::set_var("TMPDIR", &tmp);
+
+        /* the panics are expected, without a harness nobody captures their output */
+        panic::set_hook(Box::new(|_| {}));
+    }
+
+    common::main(serve, &[
+        ("poison", poison),
+        ("recover", recover),
+        ("exit", exit),
+        ("exit_stdio", exit_stdio),
+    ]);
+
+    fs::remove_dir_all(&tmp).ok();
+}
+
+fn serve(what: &str) -> Result<(), Error> {
+    let counter = Counter { count: 0 };
+
+    match what {
+        "exit" => counter.server()?.on_panic(PanicPolicy::Exit).run(),
+        "exit_stdio" => counter.server_with(StdioListener::new()?)?.on_panic(PanicPolicy::Exit).run(),
+        x => Err(Error::Server(format!("Unknown server '{}'", x))),
+    }
+}
+
+/* a server on a thread of this process */
+fn local(policy: PanicPolicy) -> Result<CounterClient, Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Counter { count: 0 }.server_with(listener)?.on_panic(policy);
+
+    thread::spawn(move || server.run_concurrent());
+
+    Counter::client_with(connector)
+}
+
+/* the panic is reported as such */
+fn failed(client: &mut CounterClient) -> Result<(), Error> {
+    match client.fail() {
+        Err(Error::Server(e)) => check(e.contains("fail was called"), &e),
+        x => Err(Error::Client(format!("expected the panic to be reported, got {:?}", x))),
+    }
+}
+
+fn poison() -> Result<(), Error> {
+    let mut client = local(PanicPolicy::Poison)?;
+    client.add(1)?;
+
+    failed(&mut client)?;
+
+    /* every later call fails */
+    match client.count() {
+        Err(Error::Server(e)) => check(e.contains("poisoned"), &e),
+        x => Err(Error::Client(format!("expected the state to be poisoned, got {:?}", x))),
+    }
+}
+
+fn recover() -> Result<(), Error> {
+    let mut client = local(PanicPolicy::Recover)?;
+    client.add(1)?;
+
+    failed(&mut client)?;
+
+    /* carries on with the state as fail left it */
+    check(client.count()? == 2, "count after the panic")
+}
+
+/* the server exits rather than answering, and doesn't leave its socket behind */
+fn exit() -> Result<(), Error> {
+    let socket = env::temp_dir().join("converse_test_panic").join("socket");
+
+    let mut server = child("exit")?.stderr(Stdio::null()).spawn()?;
+
+    let start = Instant::now();
+    while !socket.exists() && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut client = Counter::client()?;
+    check(client.add(1)? == 1, "add")?;
+    check(client.fail().is_err(), "fail returned")?;
+
+    check(server.wait()?.code() == Some(1), "exit status")?;
+    check(!socket.exists(), "socket left behind")
+}
+
+fn exit_stdio() -> Result<(), Error> {
+    let mut command = child("exit_stdio")?;
+    command.stderr(Stdio::null());
+
+    let mut client = Counter::client_from_child(command)?;
+    check(client.add(1)? == 1, "add")?;
+
+    check(client.fail().is_err(), "fail returned")?;
+
+    match client.add(1) {
+        Err(Error::Server(e)) => Err(Error::Client(format!("served after the panic: {}", e))),
+        Err(_) => Ok(()),
+        Ok(_) => Err(Error::Client("served after the panic".to_string())),
+    }
+}
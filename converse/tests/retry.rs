@@ -0,0 +1,128 @@
+/* idempotent calls retried on connection failures */
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::error::Error;
+use converse::server::PanicPolicy;
+use converse::transport::{Connector, Stream};
+use converse::transport::loopback::{self, LoopbackConnector};
+use converse_derive::Converse;
+
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_retry)]
+impl Counter {
+    #[converse(idempotent)]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    #[converse(idempotent)]
+    pub fn fail(&mut self) -> u64 {
+        self.count += 1;
+        panic!("fail was called");
+    }
+}
+
+/* refuses the first `failures` connections */
+struct Flaky {
+    inner: LoopbackConnector,
+    failures: u32,
+    attempts: Arc<AtomicU32>,
+}
+
+impl Connector for Flaky {
+    fn connect(&self) -> Result<Box<dyn Stream>, Error> {
+        match self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+            true => Err(io::Error::from(io::ErrorKind::ConnectionRefused).into()),
+            false => self.inner.connect(),
+        }
+    }
+}
+
+/* a client that fails to connect `failures` times, and its count of attempts */
+fn serve(failures: u32, attempts: u32) -> Result<(CounterClient, Arc<AtomicU32>), Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Counter { count: 0 }.server_with(listener)?.on_panic(PanicPolicy::Recover);
+    thread::spawn(move || server.run());
+
+    let tries = Arc::new(AtomicU32::new(0));
+    let mut client = Counter::client_with(Flaky {
+        inner: connector,
+        failures: failures,
+        attempts: tries.clone(),
+    })?;
+
+    client.retry().attempts = attempts;
+    client.retry().backoff = Duration::from_millis(20);
+
+    Ok((client, tries))
+}
+
+#[test]
+fn retried() -> Result<(), Error> {
+    let (client, tries) = serve(2, 3)?;
+    let start = Instant::now();
+
+    assert_eq!(client.count()?, 0);
+    assert_eq!(tries.load(Ordering::SeqCst), 3);
+
+    /* waited 20ms, then 40ms */
+    assert!(start.elapsed() >= Duration::from_millis(60));
+
+    Ok(())
+}
+
+#[test]
+fn gives_up() -> Result<(), Error> {
+    let (client, tries) = serve(5, 3)?;
+
+    match client.count() {
+        Err(Error::IOError(ref e)) if e.kind() == io::ErrorKind::ConnectionRefused => {},
+        x => panic!("expected the connection to be refused, got {:?}", x),
+    }
+
+    assert_eq!(tries.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
+
+/* a call that may have run can't be replayed */
+#[test]
+fn not_idempotent() -> Result<(), Error> {
+    let (mut client, tries) = serve(1, 3)?;
+
+    assert!(client.add(1).is_err());
+    assert_eq!(tries.load(Ordering::SeqCst), 1);
+
+    assert_eq!(client.add(1)?, 1);
+
+    Ok(())
+}
+
+/* the server got the call, its error is the answer */
+#[test]
+fn server_error() -> Result<(), Error> {
+    let (mut client, tries) = serve(0, 3)?;
+
+    match client.fail() {
+        Err(Error::Server(_)) => {},
+        x => panic!("expected a server error, got {:?}", x),
+    }
+
+    assert_eq!(tries.load(Ordering::SeqCst), 1);
+    assert_eq!(client.count()?, 1);
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+/* a child process serving its parent over stdin and stdout */
+mod common;
+
+use std::process;
+
+use converse::error::Error;
+use converse::transport::Connector;
+use converse::transport::stdio::ChildConnector;
+use converse_derive::Converse;
+
+use common::{check, child};
+
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_stdio)]
+impl Counter {
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    /* a stray println! must not end up in the responses */
+    pub fn pid(&self) -> u32 {
+        println!("pid called");
+        process::id()
+    }
+}
+
+fn main() {
+    common::main(serve, &[
+        ("round_trip", round_trip),
+        ("one_connection", one_connection),
+    ]);
+}
+
+fn serve(_: &str) -> Result<(), Error> {
+    Counter { count: 0 }.serve_stdio()
+}
+
+fn round_trip() -> Result<(), Error> {
+    let mut client = Counter::client_from_child(child("stdio")?)?;
+
+    check(client.add(2)? == 2, "add")?;
+    check(client.add(3)? == 5, "add again")?;
+    check(client.pid()? != process::id(), "served by this process")?;
+
+    let (count, pid) = client.batch().add(1).pid().send()?;
+    check(count == 6 && pid != process::id(), "batch")?;
+
+    Ok(())
+}
+
+/* the child's pipes carry one connection, the next waits for it to be dropped */
+fn one_connection() -> Result<(), Error> {
+    let connector = ChildConnector::spawn(child("stdio")?)?;
+
+    let first = connector.connect()?;
+
+    match connector.connect() {
+        Err(Error::Client(_)) => {},
+        Err(e) => return Err(e),
+        Ok(_) => return Err(Error::Client("second connection while the first is open".to_string())),
+    }
+
+    drop(first);
+    connector.connect()?;
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+/* client timeouts, and deadlines the server honours */
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::client::Channel;
+use converse::context;
+use converse::error::Error;
+use converse::transport::loopback::{self, LoopbackConnector};
+use converse_derive::Converse;
+
+struct Worker {
+    done: u64,
+}
+
+#[Converse(converse_test_timeouts)]
+impl Worker {
+    pub fn work(&mut self, ms: u64) -> u64 {
+        thread::sleep(Duration::from_millis(ms));
+        self.done += 1;
+        self.done
+    }
+
+    pub fn done(&self) -> u64 {
+        self.done
+    }
+
+    /* whether the call came with a deadline */
+    pub fn has_deadline(&self) -> bool {
+        context::current().and_then(|x| x.deadline()).is_some()
+    }
+}
+
+/* served one call at a time, so calls queue up behind slow ones */
+fn serve() -> Result<LoopbackConnector, Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Worker { done: 0 }.server_with(listener)?;
+
+    thread::spawn(move || server.run());
+
+    Ok(connector)
+}
+
+#[test]
+fn read_timeout() -> Result<(), Error> {
+    let mut client = Worker::client_with(serve()?)?;
+    client.timeouts().read = Some(Duration::from_millis(50));
+
+    let start = Instant::now();
+
+    match client.work(500) {
+        Err(Error::Timeout(_)) => {},
+        x => panic!("expected a timeout, got {:?}", x),
+    }
+
+    assert!(start.elapsed() < Duration::from_millis(400));
+
+    Ok(())
+}
+
+#[test]
+fn deadline() -> Result<(), Error> {
+    let connector = serve()?;
+
+    let mut client = Worker::client_with(connector.clone())?;
+    assert!(!client.has_deadline()?);
+
+    client.timeouts().call = Some(Duration::from_secs(5));
+    assert!(client.has_deadline()?);
+
+    /* keep the server busy, the next call waits behind this one */
+    let mut busy = Worker::client_with(connector.clone())?;
+    let slow = thread::spawn(move || busy.work(300));
+    thread::sleep(Duration::from_millis(50));
+
+    client.timeouts().call = Some(Duration::from_millis(100));
+
+    match client.work(0) {
+        Err(Error::Timeout(_)) => {},
+        x => panic!("expected a timeout, got {:?}", x),
+    }
+
+    assert_eq!(slow.join().unwrap()?, 1);
+
+    /* the server skipped the call nobody was waiting for anymore */
+    client.timeouts().call = None;
+    assert_eq!(client.done()?, 1);
+
+    Ok(())
+}
+
+/* errors from the server are said to be so once */
+#[test]
+fn server_error() -> Result<(), Error> {
+    let channel = Channel::new(Box::new(serve()?));
+
+    match channel.call(1, vec![]) {
+        Err(e @ Error::Server(_)) => assert_eq!(e.to_string(), "Server error: Invalid function called"),
+        x => panic!("expected a server error, got {:?}", x),
+    }
+
+    Ok(())
+}
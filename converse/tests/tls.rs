@@ -0,0 +1,136 @@
+/*
+ * Mutual TLS over localhost. The certificates in tests/certs are valid for
+ * a century: ca.pem signed server.pem (for 127.0.0.1) and client.pem,
+ * other.pem signed stranger.pem and is trusted by neither side.
+ */
+use std::io::prelude::*;
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::context;
+use converse::error::Error;
+use converse::transport::Peer;
+use converse::transport::tls::{self, TlsConnector, TlsListener};
+use converse_derive::Converse;
+
+struct Greeter {
+    greeting: String,
+}
+
+#[Converse(converse_test_tls)]
+impl Greeter {
+    pub fn greet(&self, name: String) -> String {
+        format!("{}, {}", self.greeting, name)
+    }
+
+    /* length of the certificate chain the caller presented */
+    pub fn certificates(&self) -> usize {
+        match context::current().map(|x| x.peer().clone()) {
+            Some(Peer::Tls { certificates, .. }) => certificates.len(),
+            _ => 0,
+        }
+    }
+}
+
+fn cert(name: &str) -> String {
+    format!("{}/tests/certs/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/* a server requiring client certificates signed by ca.pem */
+fn serve() -> Result<SocketAddr, Error> {
+
+    let config = tls::server_config(cert("server.pem"), cert("server.key"), Some(cert("ca.pem")))?;
+    let listener = TlsListener::bind("127.0.0.1:0", config)?
+        .handshake_timeout(Duration::from_secs(5));
+    let addr = listener.local_addr()?;
+
+    let mut server = Greeter { greeting: "hello".to_string() }.server_with(listener)?;
+    thread::spawn(move || server.run_concurrent());
+
+    Ok(addr)
+}
+
+fn client(addr: SocketAddr, ca: &str, identity: Option<&str>) -> Result<GreeterClient, Error> {
+
+    let identity = identity.map(|x| (cert(&format!("{}.pem", x)), cert(&format!("{}.key", x))));
+    let config = tls::client_config(cert(ca), identity)?;
+
+    Greeter::client_with(TlsConnector::new(addr, "127.0.0.1", config)?)
+}
+
+#[test]
+fn mutual() -> Result<(), Error> {
+    let addr = serve()?;
+    let client = client(addr, "ca.pem", Some("client"))?;
+
+    assert_eq!(client.greet("tls".to_string())?, "hello, tls");
+    assert_eq!(client.certificates()?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn client_without_certificate() -> Result<(), Error> {
+    let addr = serve()?;
+    let client = client(addr, "ca.pem", None)?;
+
+    match client.greet("anonymous".to_string()) {
+        Err(Error::Handshake(_)) => Ok(()),
+        x => panic!("expected a handshake error, got {:?}", x),
+    }
+}
+
+#[test]
+fn untrusted_client() -> Result<(), Error> {
+    let addr = serve()?;
+    let client = client(addr, "ca.pem", Some("stranger"))?;
+
+    match client.greet("stranger".to_string()) {
+        Err(Error::Handshake(_)) => Ok(()),
+        x => panic!("expected a handshake error, got {:?}", x),
+    }
+}
+
+#[test]
+fn untrusted_server() -> Result<(), Error> {
+    let addr = serve()?;
+    let client = client(addr, "other.pem", Some("client"))?;
+
+    match client.greet("server".to_string()) {
+        Err(Error::Handshake(_)) => Ok(()),
+        x => panic!("expected a handshake error, got {:?}", x),
+    }
+}
+
+/* shaking hands happens on the connection's thread, not in accept */
+#[test]
+fn silent_client() -> Result<(), Error> {
+    let addr = serve()?;
+
+    let _silent = TcpStream::connect(addr)?;
+    let mut half = TcpStream::connect(addr)?;
+    half.write_all(&[0x16, 0x03, 0x01])?;
+
+    let client = client(addr, "ca.pem", Some("client"))?;
+    let start = Instant::now();
+
+    assert_eq!(client.greet("again".to_string())?, "hello, again");
+    assert!(start.elapsed() < Duration::from_secs(2), "waited {:?}", start.elapsed());
+
+    Ok(())
+}
+
+/* only local peers may shut the server down */
+#[test]
+fn remote_exit() -> Result<(), Error> {
+    let addr = serve()?;
+    let mut client = client(addr, "ca.pem", Some("client"))?;
+
+    client.exit()?;
+    thread::sleep(Duration::from_millis(50));
+
+    assert_eq!(client.greet("still there".to_string())?, "hello, still there");
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+/* calls that see no other client's changes until committed */
+use std::thread;
+use std::time::{Duration, Instant};
+
+use converse::error::Error;
+use converse::transport::loopback;
+use converse_derive::Converse;
+
+#[derive(Clone)]
+struct Counter {
+    count: u64,
+}
+
+#[Converse(converse_test_transaction)]
+impl Counter {
+    pub fn add(&mut self, n: u64) -> u64 {
+        self.count += n;
+        self.count
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/* run_concurrent serves transactions on split streams, run on whole ones */
+fn serve(concurrent: bool) -> Result<CounterClient, Error> {
+
+    let (listener, connector) = loopback::pair();
+    let mut server = Counter { count: 0 }.server_with(listener)?
+        .rollback()
+        .transaction_timeout(Some(Duration::from_millis(100)));
+
+    match concurrent {
+        true => thread::spawn(move || server.run_concurrent()),
+        false => thread::spawn(move || server.run()),
+    };
+
+    Counter::client_with(connector)
+}
+
+#[test]
+fn commit() -> Result<(), Error> {
+    let client = serve(true)?;
+
+    let mut transaction = client.transaction()?;
+    assert_eq!(transaction.add(4)?, 4);
+    assert_eq!(transaction.count()?, 4);
+    transaction.commit()?;
+
+    assert_eq!(client.count()?, 4);
+
+    Ok(())
+}
+
+#[test]
+fn abort() -> Result<(), Error> {
+    let mut client = serve(true)?;
+    client.add(1)?;
+
+    let mut transaction = client.transaction()?;
+    assert_eq!(transaction.add(100)?, 101);
+    transaction.abort()?;
+
+    /* built with rollback(), so the add is undone */
+    assert_eq!(client.count()?, 1);
+
+    Ok(())
+}
+
+/* hanging up aborts */
+#[test]
+fn dropped() -> Result<(), Error> {
+    let client = serve(true)?;
+
+    let mut transaction = client.transaction()?;
+    transaction.add(7)?;
+    drop(transaction);
+
+    assert_eq!(client.count()?, 0);
+
+    Ok(())
+}
+
+/* a client that goes quiet loses the transaction instead of blocking everyone */
+fn stalled(concurrent: bool) -> Result<(), Error> {
+    let client = serve(concurrent)?;
+
+    let mut transaction = client.transaction()?;
+    transaction.add(7)?;
+
+    let start = Instant::now();
+    assert_eq!(client.count()?, 0);
+    assert!(start.elapsed() < Duration::from_secs(2), "waited {:?}", start.elapsed());
+
+    /* the server hung up on it */
+    assert!(transaction.add(1).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn stalled_concurrent() -> Result<(), Error> {
+    stalled(true)
+}
+
+#[test]
+fn stalled_sequential() -> Result<(), Error> {
+    stalled(false)
+}
@@ -2,6 +2,7 @@ use std::env;
 
 use converse;
 use converse_derive::Converse;
+use converse::autostart::AutoStart;
 use converse::serde::{Serialize, de::DeserializeOwned};
 
 struct Playlist<T>
@@ -23,8 +24,9 @@ impl<T: Serialize + Clone + DeserializeOwned> Playlist<T> {
 
 #[Converse(playlist)]
 impl<T: Serialize + Clone + DeserializeOwned> Playlist<T> {
-    pub fn add(&mut self, x: String) {
-        self.list.push(x);
+    #[converse(oneway)]
+    pub fn add(&mut self, x: &str) {
+        self.list.push(x.to_string());
     }
 
     pub fn get(&mut self, i: usize, default: String) -> String {
@@ -64,9 +66,11 @@ fn run() -> Result<(), converse::error::Error> {
     }
 
     {
-        let mut playlist = Playlist::<usize>::client()?;
+        /* start a server in the background if there isn't one yet */
+        let autostart = AutoStart::current_exe(&["server"])?;
+        let mut playlist = Playlist::<usize>::client_autostart(autostart)?;
 
-        playlist.add("Client 1".to_string())?;
+        playlist.add("Client 1")?;
         println!("list: {:?}", playlist.list()?);
         println!("data: {:?}", playlist.data()?);
     }
@@ -74,9 +78,12 @@ fn run() -> Result<(), converse::error::Error> {
     {
         let mut playlist = Playlist::<usize>::client()?;
 
-        playlist.add("Client 2".to_string())?;
-        println!("list: {:?}", playlist.list()?);
-        println!("data: {:?}", playlist.data()?);
+        playlist.add("Client 2")?;
+
+        /* both in one round trip */
+        let (list, data) = playlist.batch().list().data().send()?;
+        println!("list: {:?}", list);
+        println!("data: {:?}", data);
         playlist.exit()?;
     }
 